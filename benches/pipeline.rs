@@ -0,0 +1,183 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    io::Cursor,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use ascii_art::{render_frame, ColorMode, Filter, FrameData, Ramp, RenderOptions, Sample};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use image::{DynamicImage, ImageFormat, RgbImage};
+
+const OPTIONS: RenderOptions = RenderOptions {
+    ramp: Ramp::Short,
+    color: ColorMode::Truecolor,
+    filter: Filter::Nearest,
+    sample: Sample::Point,
+    pixel_width: 2,
+    cell_aspect: 2.0,
+    braille: false,
+    half_block: false,
+    dither: false,
+    edges: false,
+    edge_threshold: None,
+    diff: false,
+    gamma: false,
+    luma: None,
+    lab: false,
+    color_levels: None,
+    posterize: None,
+    contrast: 1.0,
+    brightness: 0.0,
+    background: None,
+    invert: false,
+    block_rows: None,
+    graphics: None,
+    seed: 0,
+    crop: None,
+};
+
+/// The system allocator, counting the allocations made through it so `allocations`
+/// can tell how many converting a frame takes.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// A 1080p frame with gradients and some fine detail, like a video frame would have.
+fn source_frame() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(1920, 1080, |x, y| {
+        image::Rgb([
+            (x * 255 / 1920) as u8,
+            (y * 255 / 1080) as u8,
+            ((x ^ y) % 256) as u8,
+        ])
+    }))
+}
+
+/// Frame sizes in cells, from a small terminal up to a full-screen one.
+const SIZES: [(u32, u32); 3] = [(80, 45), (160, 90), (320, 180)];
+
+fn convert(c: &mut Criterion) {
+    let img = source_frame();
+    let mut group = c.benchmark_group("render_frame");
+    for (width, height) in SIZES {
+        let mut frame = FrameData::new(width, height);
+        group.bench_with_input(
+            BenchmarkId::new("nearest", format!("{width}x{height}")),
+            &img,
+            |b, img| b.iter(|| render_frame(black_box(img), &mut frame, None, &OPTIONS)),
+        );
+        let options = RenderOptions {
+            filter: Filter::Triangle,
+            ..OPTIONS
+        };
+        group.bench_with_input(
+            BenchmarkId::new("triangle", format!("{width}x{height}")),
+            &img,
+            |b, img| b.iter(|| render_frame(black_box(img), &mut frame, None, &options)),
+        );
+        // Diffing against the same frame is the best case, nothing gets written
+        render_frame(&img, &mut frame, None, &OPTIONS);
+        let previous = frame.cells.clone();
+        group.bench_with_input(
+            BenchmarkId::new("diff", format!("{width}x{height}")),
+            &img,
+            |b, img| b.iter(|| render_frame(black_box(img), &mut frame, Some(&previous), &OPTIONS)),
+        );
+    }
+    group.finish();
+}
+
+/// Converting on pools of a few sizes, and in blocks of a few heights, like
+/// `--threads` and `--block-rows` do. On a machine with fewer cores than threads the
+/// larger pools show what oversubscription costs.
+fn threads(c: &mut Criterion) {
+    let img = source_frame();
+    let (width, height) = SIZES[1];
+    let mut frame = FrameData::new(width, height);
+    let mut group = c.benchmark_group("threads");
+    for threads in [1, 2, 4, 8] {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        group.bench_with_input(BenchmarkId::new("pool", threads), &img, |b, img| {
+            b.iter(|| pool.install(|| render_frame(black_box(img), &mut frame, None, &OPTIONS)))
+        });
+    }
+    for block_rows in [1, 8, 32] {
+        let options = RenderOptions {
+            block_rows: Some(block_rows),
+            ..OPTIONS
+        };
+        group.bench_with_input(
+            BenchmarkId::new("block_rows", block_rows),
+            &img,
+            |b, img| b.iter(|| render_frame(black_box(img), &mut frame, None, &options)),
+        );
+    }
+    group.finish();
+}
+
+/// Decoding and converting a JPEG held in memory, everything the player does for a
+/// frame short of writing it to the terminal.
+fn pipeline(c: &mut Criterion) {
+    let mut jpeg = Vec::new();
+    source_frame()
+        .write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+        .unwrap();
+    let (width, height) = SIZES[1];
+    let mut frame = FrameData::new(width, height);
+    c.bench_function("decode_and_render", |b| {
+        b.iter(|| {
+            let img = image::load_from_memory(black_box(&jpeg)).unwrap();
+            render_frame(&img, &mut frame, None, &OPTIONS);
+        })
+    });
+}
+
+/// Allocations converting a frame takes once the frame's buffers have grown to fit,
+/// printed rather than timed, on pools of a few sizes.
+fn allocations(_: &mut Criterion) {
+    let img = source_frame();
+    let (width, height) = SIZES[1];
+    for threads in [1, 4] {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let mut frame = FrameData::new(width, height);
+        pool.install(|| render_frame(&img, &mut frame, None, &OPTIONS));
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        for _ in 0..100 {
+            pool.install(|| render_frame(black_box(&img), &mut frame, None, &OPTIONS));
+        }
+        let count = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!(
+            "allocations/pool {threads}: {} per frame",
+            count as f64 / 100.0
+        );
+    }
+}
+
+criterion_group!(benches, convert, threads, pipeline, allocations);
+criterion_main!(benches);
@@ -0,0 +1,105 @@
+//! The sound track played along with the frames, whose position paces them.
+
+use std::{path::Path, time::Duration};
+
+#[cfg(feature = "audio")]
+use anyhow::Context;
+
+/// Keeps the audio device open while a track plays on it. It stays on the thread
+/// that opened it and has to outlive the [`Audio`].
+#[cfg(feature = "audio")]
+pub struct Output {
+    _sink: rodio::MixerDeviceSink,
+}
+
+/// A sound track, paused until the first frame is shown.
+#[cfg(feature = "audio")]
+pub struct Audio {
+    player: rodio::Player,
+    path: std::path::PathBuf,
+    /// Where the first frame sits on the track, later than the start for a clip
+    offset: Duration,
+}
+
+#[cfg(feature = "audio")]
+impl Audio {
+    /// Opens the default output device and the track at `path`, with the first frame
+    /// `offset` into it.
+    pub fn open(path: &Path, offset: Duration) -> anyhow::Result<(Output, Audio)> {
+        let mut sink = rodio::DeviceSinkBuilder::open_default_sink()
+            .context("failed to open the audio output")?;
+        // It would say so on stderr at exit otherwise
+        sink.log_on_drop(false);
+        let player = rodio::Player::connect_new(sink.mixer());
+        player.pause();
+        let audio = Audio {
+            player,
+            path: path.to_path_buf(),
+            offset,
+        };
+        audio.load()?;
+        Ok((Output { _sink: sink }, audio))
+    }
+
+    fn load(&self) -> anyhow::Result<()> {
+        let file = std::fs::File::open(&self.path)
+            .with_context(|| format!("failed to open {}", self.path.display()))?;
+        let source = rodio::Decoder::try_from(file)
+            .with_context(|| format!("failed to decode {}", self.path.display()))?;
+        self.player.append(source);
+        Ok(())
+    }
+
+    /// How far the track has played past the first frame, `None` once it has ended.
+    pub fn position(&self) -> Option<Duration> {
+        (!self.player.empty()).then(|| self.player.get_pos().saturating_sub(self.offset))
+    }
+
+    /// Moves the track to `position` past the first frame, starting it over if it
+    /// has ended. Formats that can't seek keep playing where they are.
+    pub fn seek(&self, position: Duration) {
+        if self.player.empty() && self.load().is_err() {
+            return;
+        }
+        let _ = self.player.try_seek(self.offset + position);
+    }
+
+    pub fn play(&self) {
+        self.player.play();
+    }
+
+    pub fn pause(&self) {
+        self.player.pause();
+    }
+}
+
+/// Without the `audio` feature there's no output to open.
+#[cfg(not(feature = "audio"))]
+pub struct Output;
+
+/// Without the `audio` feature there are no tracks, [`Audio::open`] always fails.
+#[cfg(not(feature = "audio"))]
+pub struct Audio(std::convert::Infallible);
+
+#[cfg(not(feature = "audio"))]
+impl Audio {
+    pub fn open(_path: &Path, _offset: Duration) -> anyhow::Result<(Output, Audio)> {
+        anyhow::bail!("this build plays no audio, build it with `--features audio` for --audio")
+    }
+
+    pub fn position(&self) -> Option<Duration> {
+        match self.0 {}
+    }
+
+    pub fn seek(&self, _position: Duration) {
+        match self.0 {}
+    }
+
+    pub fn play(&self) {
+        match self.0 {}
+    }
+
+    pub fn pause(&self) {
+        match self.0 {}
+    }
+}
@@ -0,0 +1,18 @@
+//! When frames go on screen. Each frame is due a frame time after the one before it
+//! was due, not after it was shown, so time spent drawing or oversleeping never adds
+//! up into drift.
+
+use std::time::{Duration, Instant};
+
+/// When the next frame is due: `frame_time` after `deadline`, the time the frame
+/// before it was due, or after `now` when there's none to go by, as for the first
+/// frame and after a pause.
+pub fn next_deadline(deadline: Option<Instant>, now: Instant, frame_time: Duration) -> Instant {
+    deadline.unwrap_or(now) + frame_time
+}
+
+/// Whole frames the frame due at `due` is late by when it goes up at `now`, those
+/// to skip to keep to the clock.
+pub fn frames_behind(due: Instant, now: Instant, frame_time: Duration) -> u32 {
+    (now.saturating_duration_since(due).as_nanos() / frame_time.as_nanos().max(1)) as u32
+}
@@ -0,0 +1,150 @@
+//! Defaults for the command-line options, read from a TOML file so they needn't be
+//! passed on every run.
+
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+/// File looked for in the working directory when `--config` isn't given.
+pub const CONFIG_FILE: &str = "ascii_art.toml";
+
+/// Options a config file may set, each named like its flag. The values are checked
+/// the same way as on the command line, where they are passed on to.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    scale: Option<u32>,
+    fps: Option<u32>,
+    #[serde(rename = "loop")]
+    loop_count: Option<u32>,
+    transition: Option<String>,
+    transition_ms: Option<u64>,
+    braille: Option<bool>,
+    pixel_width: Option<u32>,
+    cell_aspect: Option<f32>,
+    half_block: Option<bool>,
+    diff: Option<bool>,
+    dedupe: Option<bool>,
+    gamma: Option<bool>,
+    luma: Option<String>,
+    lab: Option<bool>,
+    colors: Option<u32>,
+    posterize: Option<u8>,
+    contrast: Option<f32>,
+    brightness: Option<f32>,
+    bg: Option<String>,
+    invert: Option<bool>,
+    edges: Option<bool>,
+    edge_threshold: Option<f32>,
+    dither: Option<bool>,
+    no_skip: Option<bool>,
+    idle_timeout: Option<u64>,
+    exit_on_idle: Option<bool>,
+    strict: Option<bool>,
+    no_auto_rotate: Option<bool>,
+    keep_last: Option<bool>,
+    title: Option<bool>,
+    cache: Option<usize>,
+    lookahead: Option<u16>,
+    filter: Option<String>,
+    sample: Option<String>,
+    threads: Option<u16>,
+    block_rows: Option<u32>,
+    seed: Option<u64>,
+    ramp: Option<String>,
+    color: Option<String>,
+    align: Option<String>,
+    crop: Option<String>,
+    protocol: Option<String>,
+    status: Option<bool>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Config> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// The options the file sets, by the id of their argument, along with the value
+    /// to pass. Flags set to `false` are left out as they're off anyway, flags set to
+    /// `true` come without a value.
+    pub fn args(&self) -> Vec<(&'static str, Option<String>)> {
+        let mut args = Vec::new();
+        let mut value = |id: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                args.push((id, Some(value)));
+            }
+        };
+        value("scale", self.scale.map(|scale| scale.to_string()));
+        value("fps", self.fps.map(|fps| fps.to_string()));
+        value("loop_count", self.loop_count.map(|loops| loops.to_string()));
+        value("transition", self.transition.clone());
+        value("transition_ms", self.transition_ms.map(|ms| ms.to_string()));
+        value(
+            "pixel_width",
+            self.pixel_width.map(|width| width.to_string()),
+        );
+        value(
+            "cell_aspect",
+            self.cell_aspect.map(|ratio| ratio.to_string()),
+        );
+        value(
+            "contrast",
+            self.contrast.map(|contrast| contrast.to_string()),
+        );
+        value(
+            "brightness",
+            self.brightness.map(|brightness| brightness.to_string()),
+        );
+        value("bg", self.bg.clone());
+        value(
+            "edge_threshold",
+            self.edge_threshold.map(|threshold| threshold.to_string()),
+        );
+        value("luma", self.luma.clone());
+        value("colors", self.colors.map(|colors| colors.to_string()));
+        value("posterize", self.posterize.map(|bits| bits.to_string()));
+        value(
+            "idle_timeout",
+            self.idle_timeout.map(|seconds| seconds.to_string()),
+        );
+        value("cache", self.cache.map(|frames| frames.to_string()));
+        value("lookahead", self.lookahead.map(|frames| frames.to_string()));
+        value("filter", self.filter.clone());
+        value("sample", self.sample.clone());
+        value("threads", self.threads.map(|threads| threads.to_string()));
+        value("block_rows", self.block_rows.map(|rows| rows.to_string()));
+        value("seed", self.seed.map(|seed| seed.to_string()));
+        value("ramp", self.ramp.clone());
+        value("color", self.color.clone());
+        value("align", self.align.clone());
+        value("crop", self.crop.clone());
+        value("protocol", self.protocol.clone());
+        let flags = [
+            ("braille", self.braille),
+            ("half_block", self.half_block),
+            ("diff", self.diff),
+            ("dedupe", self.dedupe),
+            ("gamma", self.gamma),
+            ("lab", self.lab),
+            ("invert", self.invert),
+            ("edges", self.edges),
+            ("dither", self.dither),
+            ("no_skip", self.no_skip),
+            ("exit_on_idle", self.exit_on_idle),
+            ("strict", self.strict),
+            ("no_auto_rotate", self.no_auto_rotate),
+            ("keep_last", self.keep_last),
+            ("title", self.title),
+            ("status", self.status),
+        ];
+        for (id, set) in flags {
+            if set == Some(true) {
+                args.push((id, None));
+            }
+        }
+        args
+    }
+}
@@ -0,0 +1,1549 @@
+//! Conversion of images into terminal cells and the escape sequences that draw
+//! them.
+
+use std::{borrow::Cow, io::Write, ops::Range, sync::LazyLock, time::Duration};
+
+use anyhow::bail;
+use clap::ValueEnum;
+use image::{DynamicImage, GenericImageView};
+use rayon::{
+    iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+use unicode_width::UnicodeWidthChar;
+
+use crate::graphics::{render_graphics, Graphics};
+
+// Glyphs ordered from sparse to dense, so dark pixels stay mostly empty on a dark
+// terminal.
+const SHORT_RAMP: &[u8] = b" .:-=+*#%@";
+const LONG_RAMP: &[u8] =
+    b" .'`^\",:;Il!i><~+_-?][}{1)(|\\/tfjrxnuvczXYUJCLQ0OZmwqpdbkhao*#MW&8%B@$";
+
+/// Glyphs luminance is mapped onto.
+#[derive(Clone, Copy)]
+pub enum Ramp {
+    /// 10 glyphs, good for small sizes
+    Short,
+    /// 70 glyphs, finer shading at larger sizes
+    Long,
+    /// Glyphs of the user's own, for the darkest pixels first. Must not be empty
+    Custom(&'static [char]),
+}
+
+impl Ramp {
+    fn len(self) -> usize {
+        match self {
+            Ramp::Short => SHORT_RAMP.len(),
+            Ramp::Long => LONG_RAMP.len(),
+            Ramp::Custom(glyphs) => glyphs.len(),
+        }
+    }
+
+    fn get(self, index: usize) -> char {
+        match self {
+            Ramp::Short => SHORT_RAMP[index] as char,
+            Ramp::Long => LONG_RAMP[index] as char,
+            Ramp::Custom(glyphs) => glyphs[index],
+        }
+    }
+
+    fn glyph(self, luma: f32) -> char {
+        self.quantize(luma).0
+    }
+
+    /// Nearest glyph for `luma`, along with the luminance that glyph stands for.
+    fn quantize(self, luma: f32) -> (char, f32) {
+        // A single glyph stands for everything
+        let steps = (self.len() - 1).max(1) as f32;
+        let index = (luma.clamp(0.0, 255.0) / 255.0 * steps).round() as usize;
+        (
+            self.get(index.min(self.len() - 1)),
+            index as f32 / steps * 255.0,
+        )
+    }
+
+    /// Glyphs of the ramp that can't be drawn in a cell `columns` wide: control
+    /// characters and the like, which show as spaces, and glyphs wider than the cell.
+    pub fn undrawable(self, columns: u32) -> Vec<char> {
+        (0..self.len())
+            .map(|index| self.get(index))
+            .filter(|&glyph| glyph_width(glyph).is_none_or(|width| width > columns))
+            .collect()
+    }
+}
+
+/// How cell colors are written.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ColorMode {
+    /// 24-bit foreground colors
+    Truecolor,
+    /// Nearest color of the xterm 256-color palette
+    Ansi256,
+    /// Glyphs only, no color escapes
+    Mono,
+}
+
+impl ColorMode {
+    /// Best mode the terminal running us advertises, see [`terminal_caps`]. No color
+    /// at all when `$NO_COLOR` is set, to anything, as <https://no-color.org> asks.
+    /// Windows Terminal sets neither variable but shows 24-bit colors, it's told by
+    /// the `$WT_SESSION` it gives its programs.
+    pub fn detect() -> Self {
+        if std::env::var_os("NO_COLOR").is_some() {
+            return ColorMode::Mono;
+        }
+        if std::env::var_os("WT_SESSION").is_some() {
+            return ColorMode::Truecolor;
+        }
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        terminal_caps(&colorterm, &term)
+    }
+}
+
+/// Best color mode a terminal advertises through the values of `$COLORTERM` and
+/// `$TERM`: truecolor when either says so, no color for a `dumb` terminal, and the
+/// 256-color palette otherwise. Far more terminals understand the palette than 24-bit
+/// colors, which the rest either ignore or show approximately.
+pub fn terminal_caps(colorterm: &str, term: &str) -> ColorMode {
+    let truecolor = matches!(colorterm, "truecolor" | "24bit")
+        || term.ends_with("-direct")
+        || term.contains("truecolor")
+        || term.contains("24bit");
+    if truecolor {
+        ColorMode::Truecolor
+    } else if term == "dumb" {
+        ColorMode::Mono
+    } else {
+        ColorMode::Ansi256
+    }
+}
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn nearest_cube_level(value: u8) -> usize {
+    // Midpoints between neighbouring levels of the color cube
+    match value {
+        0..=47 => 0,
+        48..=114 => 1,
+        115..=154 => 2,
+        155..=194 => 3,
+        195..=234 => 4,
+        _ => 5,
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let dr = r1 as i32 - r2 as i32;
+    let dg = g1 as i32 - g2 as i32;
+    let db = b1 as i32 - b2 as i32;
+    (dr * dr + dg * dg + db * db) as u32
+}
+
+/// Nearest xterm-256 index, searching the 6x6x6 color cube and the 24-step grayscale
+/// ramp. The 16 system colors are skipped since terminal themes redefine them. With
+/// `lab` the distance is measured in CIELAB rather than in sRGB.
+pub(crate) fn ansi256(r: u8, g: u8, b: u8, lab: bool) -> u8 {
+    if lab {
+        return ansi256_lab(r, g, b);
+    }
+    let (ri, gi, bi) = (
+        nearest_cube_level(r),
+        nearest_cube_level(g),
+        nearest_cube_level(b),
+    );
+    let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    // The grayscale ramp runs from 8 to 238 in steps of 10
+    let step = ((average.max(8) - 8 + 5) / 10).min(23);
+    let gray = (8 + step * 10) as u8;
+    if distance((r, g, b), (gray, gray, gray)) < distance((r, g, b), cube) {
+        232 + step as u8
+    } else {
+        (16 + 36 * ri + 6 * gi + bi) as u8
+    }
+}
+
+/// The color of xterm-256 index `index`, from 16 up.
+pub(crate) fn palette_rgb(index: u8) -> [u8; 3] {
+    match index {
+        232.. => [8 + (index - 232) * 10; 3],
+        _ => {
+            let cube = (index - 16) as usize;
+            [
+                CUBE_LEVELS[cube / 36],
+                CUBE_LEVELS[cube / 6 % 6],
+                CUBE_LEVELS[cube % 6],
+            ]
+        }
+    }
+}
+
+/// CIELAB coordinates of the palette from index 16 up, the ones [`ansi256`] picks from.
+static PALETTE_LAB: LazyLock<[[f32; 3]; 240]> =
+    LazyLock::new(|| std::array::from_fn(|i| rgb_to_lab(palette_rgb(16 + i as u8))));
+
+/// Nearest xterm-256 index by the CIE76 color difference, which follows what the
+/// eye tells apart much better than sRGB distance, in dark and saturated colors
+/// above all. The whole palette is searched since the nearest cube level per
+/// channel needn't be the nearest color.
+fn ansi256_lab(r: u8, g: u8, b: u8) -> u8 {
+    let [l, a, b] = rgb_to_lab([r, g, b]);
+    let delta = |&[l2, a2, b2]: &[f32; 3]| (l - l2).powi(2) + (a - a2).powi(2) + (b - b2).powi(2);
+    let (nearest, _) = PALETTE_LAB
+        .iter()
+        .enumerate()
+        .min_by(|(_, x), (_, y)| delta(x).total_cmp(&delta(y)))
+        .expect("the palette isn't empty");
+    16 + nearest as u8
+}
+
+/// CIELAB coordinates of an sRGB color, as `[L, a, b]` under the D65 white point.
+pub fn rgb_to_lab([r, g, b]: [u8; 3]) -> [f32; 3] {
+    let (r, g, b) = (
+        SRGB_TO_LINEAR[r as usize],
+        SRGB_TO_LINEAR[g as usize],
+        SRGB_TO_LINEAR[b as usize],
+    );
+    // Relative to the white point, so white lands on 1 in each
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// The sRGB color at CIELAB coordinates `[L, a, b]`, the inverse of [`rgb_to_lab`].
+/// Colors outside of sRGB are clamped into it.
+fn lab_to_rgb([l, a, b]: [f32; 3]) -> [u8; 3] {
+    let fy = (l + 16.0) / 116.0;
+    let (fx, fz) = (fy + a / 500.0, fy - b / 200.0);
+    let f_inv = |t: f32| {
+        if t > 6.0 / 29.0 {
+            t.powi(3)
+        } else {
+            (116.0 * t - 16.0) * 27.0 / 24389.0
+        }
+    };
+    let (x, y, z) = (f_inv(fx) * 0.95047, f_inv(fy), f_inv(fz) * 1.08883);
+    let encode = |linear: f32| {
+        let linear = linear.clamp(0.0, 1.0);
+        let encoded = if linear <= 0.0031308 {
+            linear * 12.92
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        };
+        (encoded * 255.0).round() as u8
+    };
+    [
+        encode(3.2406 * x - 1.5372 * y - 0.4986 * z),
+        encode(-0.9689 * x + 1.8758 * y + 0.0415 * z),
+        encode(0.0557 * x - 0.2040 * y + 1.0570 * z),
+    ]
+}
+
+/// Blend of `from` and `to`, a fraction `t` of the way from one to the other, for
+/// crossfades. Colors are mixed channel by channel, or in CIELAB with `lab`, which
+/// passes through less muddy hues on the way. `to` is scaled to the size of `from`
+/// when it differs.
+pub fn crossfade(from: &DynamicImage, to: &DynamicImage, t: f32, lab: bool) -> DynamicImage {
+    let from = from.to_rgba8();
+    let to = match to.dimensions() == from.dimensions() {
+        true => to.to_rgba8(),
+        false => Filter::Triangle
+            .resize(to, from.width(), from.height())
+            .to_rgba8(),
+    };
+    let mix = |a: f32, b: f32| a + (b - a) * t;
+    let blended = image::RgbaImage::from_fn(from.width(), from.height(), |x, y| {
+        let [r1, g1, b1, a1] = from.get_pixel(x, y).0;
+        let [r2, g2, b2, a2] = to.get_pixel(x, y).0;
+        let alpha = mix(a1 as f32, a2 as f32).round() as u8;
+        let [r, g, b] = if lab {
+            let (from, to) = (rgb_to_lab([r1, g1, b1]), rgb_to_lab([r2, g2, b2]));
+            lab_to_rgb([0, 1, 2].map(|i| mix(from[i], to[i])))
+        } else {
+            [(r1, r2), (g1, g2), (b1, b2)].map(|(a, b)| mix(a as f32, b as f32).round() as u8)
+        };
+        image::Rgba([r, g, b, alpha])
+    });
+    DynamicImage::ImageRgba8(blended)
+}
+
+/// Where a frame sits on a terminal with room to spare around it.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Align {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Align {
+    /// Terminal column and row, counting from 0, a `width`x`height` frame of cells is
+    /// drawn at on a `cols`x`rows` terminal. The room left over is blank margin; a
+    /// frame larger than the terminal stays at the top left.
+    pub fn origin(
+        self,
+        (width, height): (u32, u32),
+        (cols, rows): (u16, u16),
+        options: &RenderOptions,
+    ) -> (u32, u32) {
+        let spare_cols = (cols as u32).saturating_sub(width * options.cell_columns());
+        let spare_rows = (rows as u32).saturating_sub(height);
+        // Halves of the spare room that go before the frame, across and down
+        let (x, y) = match self {
+            Align::TopLeft => (0, 0),
+            Align::Top => (1, 0),
+            Align::TopRight => (2, 0),
+            Align::Left => (0, 1),
+            Align::Center => (1, 1),
+            Align::Right => (2, 1),
+            Align::BottomLeft => (0, 2),
+            Align::Bottom => (1, 2),
+            Align::BottomRight => (2, 2),
+        };
+        (spare_cols * x / 2, spare_rows * y / 2)
+    }
+}
+
+/// Rows of the terminal kept free of the frame, `top` above it for a title and
+/// `bottom` below it for the status line. Fitting and placing a frame both go
+/// through it, so the frame and whatever is drawn around it use the same rows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Layout {
+    pub top: u16,
+    pub bottom: u16,
+}
+
+impl Layout {
+    /// Rows a terminal `rows` tall leaves to the frame.
+    pub fn frame_rows(self, rows: u16) -> u16 {
+        rows.saturating_sub(self.top.saturating_add(self.bottom))
+    }
+
+    /// Like [`fit_cells`], for the rows left to the frame.
+    pub fn fit(
+        self,
+        source: (u32, u32),
+        (cols, rows): (u16, u16),
+        options: &RenderOptions,
+    ) -> (u32, u32) {
+        fit_cells(source, (cols, self.frame_rows(rows)), options)
+    }
+
+    /// Like [`Align::origin`], within the rows left to the frame and below those
+    /// kept free at the top.
+    pub fn origin(
+        self,
+        align: Align,
+        size: (u32, u32),
+        (cols, rows): (u16, u16),
+        options: &RenderOptions,
+    ) -> (u32, u32) {
+        let (x, y) = align.origin(size, (cols, self.frame_rows(rows)), options);
+        (x, y + self.top as u32)
+    }
+}
+
+/// How a source frame is scaled down to the frame's pixel grid.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Filter {
+    /// Pick the nearest source pixel, fastest but aliases fine detail
+    Nearest,
+    /// Bilinear filtering
+    Triangle,
+    /// Lanczos with a window of 3, sharpest and slowest
+    Lanczos3,
+}
+
+impl Filter {
+    pub(crate) fn filter_type(self) -> image::imageops::FilterType {
+        match self {
+            Filter::Nearest => image::imageops::FilterType::Nearest,
+            Filter::Triangle => image::imageops::FilterType::Triangle,
+            Filter::Lanczos3 => image::imageops::FilterType::Lanczos3,
+        }
+    }
+
+    /// `img` scaled to exactly `width`x`height`. The colors of a picture with alpha
+    /// are weighted by it while they're mixed, so the transparent pixels around an
+    /// anti-aliased edge, black more often than not, don't darken it.
+    pub(crate) fn resize(self, img: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+        // Picking pixels mixes nothing
+        if !img.color().has_alpha() || matches!(self, Filter::Nearest) {
+            return img.resize_exact(width, height, self.filter_type());
+        }
+        let mut premultiplied = img.to_rgba8();
+        for pixel in premultiplied.pixels_mut() {
+            let alpha = pixel[3] as u32;
+            for channel in &mut pixel.0[..3] {
+                *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+            }
+        }
+        let mut resized =
+            image::imageops::resize(&premultiplied, width, height, self.filter_type());
+        for pixel in resized.pixels_mut() {
+            let alpha = pixel[3] as u32;
+            // Fully transparent pixels keep the black they come out as
+            for channel in &mut pixel.0[..3] {
+                let straight = (*channel as u32 * 255 + alpha / 2).checked_div(alpha);
+                *channel = straight.unwrap_or(0).min(255) as u8;
+            }
+        }
+        DynamicImage::ImageRgba8(resized)
+    }
+}
+
+/// Weights of the red, green and blue channels in the luminance glyphs are picked by.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Luma {
+    /// Rec. 601, the weights of SD video that classic ASCII art tools use
+    Rec601,
+    /// Rec. 709, those of HD video and sRGB, which count green for more
+    Rec709,
+    /// All three channels alike
+    Average,
+}
+
+impl Luma {
+    fn weights(self) -> [f32; 3] {
+        match self {
+            Luma::Rec601 => [0.299, 0.587, 0.114],
+            Luma::Rec709 => [0.2126, 0.7152, 0.0722],
+            Luma::Average => [1.0 / 3.0; 3],
+        }
+    }
+}
+
+/// How the color of a grid pixel is read from the source pixels it covers.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Sample {
+    /// The one pixel at its corner, fastest but shimmers on fine detail in motion
+    Point,
+    /// The mean of all of them, weighed by their alpha
+    Average,
+}
+
+/// How frames are converted into cells and drawn.
+#[derive(Clone, Copy)]
+pub struct RenderOptions {
+    pub ramp: Ramp,
+    pub color: ColorMode,
+    pub filter: Filter,
+    /// How grid pixels are read from the source. A `filter` other than
+    /// [`Filter::Nearest`] scales the frame to the grid first, leaving one source
+    /// pixel to each
+    pub sample: Sample,
+    /// Terminal columns a plain pixel is drawn across, 2 makes up for cells being
+    /// about twice as tall as wide
+    pub pixel_width: u32,
+    /// Height of a terminal cell over its width, 2 for most fonts. Frames are
+    /// stretched or squeezed vertically by as much as the pixels drawn across
+    /// `pixel_width` columns, or the dots of a braille or half block cell, fall short
+    /// of square
+    pub cell_aspect: f32,
+    /// Draw 2x4 pixel blocks as braille glyphs instead of one glyph per pixel
+    pub braille: bool,
+    /// Draw two pixels per cell with `▀`, the top one as foreground and the bottom one
+    /// as background
+    pub half_block: bool,
+    /// Spread the error of each glyph onto its neighbours, Floyd-Steinberg style
+    pub dither: bool,
+    /// Draw the outlines of the image with line glyphs instead of shading it
+    pub edges: bool,
+    /// Shade with the ramp, but draw line glyphs along edges at least this strong, as
+    /// a share of a step from black to white
+    pub edge_threshold: Option<f32>,
+    /// Only draw the cells that changed since the previous frame
+    pub diff: bool,
+    /// Compute luminance from linear light rather than the raw sRGB bytes
+    pub gamma: bool,
+    /// Weights luminance is computed with. `None` takes Rec. 601 for the sRGB bytes
+    /// and Rec. 709, which sRGB is defined with, for linear light
+    pub luma: Option<Luma>,
+    /// Match colors to the 256-color palette by their CIELAB difference rather than
+    /// by sRGB distance
+    pub lab: bool,
+    /// Levels each channel of the cell colors is rounded to, from 2 up, so that
+    /// neighbouring cells share colors more often and the escapes between them can
+    /// be left out. `None` keeps the colors as they are
+    pub color_levels: Option<u8>,
+    /// Bits each channel of a pixel keeps, from 1 to 8, after the contrast and
+    /// brightness. The bits kept are repeated into the ones dropped so white stays
+    /// white; 2 bits leave 0, 85, 170 and 255. `None` keeps all 8
+    pub posterize: Option<u8>,
+    /// Factor stretching the colors away from mid-gray, 1 leaves them as they are
+    pub contrast: f32,
+    /// Added to the colors after the contrast, as a fraction of full brightness
+    pub brightness: f32,
+    /// Color transparent pixels are composited over, without one they are left out
+    /// and show the terminal's background
+    pub background: Option<[u8; 3]>,
+    /// Flip the ramp so dense glyphs stand for dark pixels, for dark text on a light
+    /// terminal. Colors stay as they are
+    pub invert: bool,
+    /// Rows converted at a time by each thread, `None` splits the frame into one block
+    /// per thread. Dithering doesn't carry its error across blocks
+    pub block_rows: Option<u32>,
+    /// Send frames as pictures through a terminal graphics protocol instead of
+    /// drawing them with glyphs, which leaves out the glyph options
+    pub graphics: Option<Graphics>,
+    /// Seed for anything conversion picks at random, so the same seed, options and
+    /// frame always give the same bytes. Nothing is picked at random yet; features
+    /// that add noise or jitter draw it from here rather than from the clock
+    pub seed: u64,
+    /// Region of the frame to convert, as `(x, y, width, height)` in pixels, cut out
+    /// before it's scaled to the cells. Parts past the frame's edges are left out.
+    /// `None` converts all of it
+    pub crop: Option<(u32, u32, u32, u32)>,
+}
+
+impl RenderOptions {
+    /// Builder starting from the same defaults as the command line.
+    pub fn builder() -> RenderOptionsBuilder {
+        RenderOptionsBuilder::default()
+    }
+
+    /// Source pixels covered by one cell, as columns and rows.
+    pub fn cell_pixels(&self) -> (u32, u32) {
+        if self.braille {
+            (2, 4)
+        } else if self.half_block {
+            (1, 2)
+        } else {
+            (1, 1)
+        }
+    }
+
+    /// `source` in pixels with its height scaled so that, once a cell draws its
+    /// [`cell_pixels`](Self::cell_pixels), the frame keeps the shape of the source on
+    /// cells `cell_aspect` times as tall as wide.
+    pub fn cell_shape(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        let (cell_width, cell_height) = self.cell_pixels();
+        let stretch = (self.cell_columns() * cell_height) as f64
+            / (self.cell_aspect as f64 * cell_width as f64);
+        let height = match height {
+            0 => 0,
+            height => ((height as f64 * stretch).round() as u32).max(1),
+        };
+        (width, height)
+    }
+
+    /// Luminance of a pixel from 0 to 255, as the glyphs are picked by.
+    fn luminance(&self, [r, g, b]: [u8; 3]) -> f32 {
+        let luma = if self.gamma {
+            linear_luminance(r, g, b, self.luma.unwrap_or(Luma::Rec709))
+        } else {
+            luminance(r, g, b, self.luma.unwrap_or(Luma::Rec601))
+        };
+        if self.invert {
+            255.0 - luma
+        } else {
+            luma
+        }
+    }
+
+    /// Rounds a cell color to the nearest of the `color_levels`.
+    fn quantize(&self, color: [u8; 3]) -> [u8; 3] {
+        let Some(levels) = self.color_levels else {
+            return color;
+        };
+        let steps = (levels.max(2) - 1) as u32;
+        color.map(|channel| ((channel as u32 * steps + 127) / 255 * 255 / steps) as u8)
+    }
+
+    /// Keeps the top `posterize` bits of each channel of a pixel.
+    fn posterize(&self, pixel: [u8; 3]) -> [u8; 3] {
+        let Some(bits) = self.posterize.filter(|&bits| bits < 8) else {
+            return pixel;
+        };
+        let bits = bits.max(1);
+        pixel.map(|channel| {
+            let kept = channel & !(0xff >> bits);
+            (0..8)
+                .step_by(bits as usize)
+                .fold(0, |value, shift| value | kept >> shift)
+        })
+    }
+
+    /// Applies the contrast, then the brightness, to each channel of a pixel.
+    fn adjust(&self, pixel: [u8; 3]) -> [u8; 3] {
+        if self.contrast == 1.0 && self.brightness == 0.0 {
+            return pixel;
+        }
+        pixel.map(|channel| {
+            let value = (channel as f32 - 127.5) * self.contrast + 127.5 + self.brightness * 255.0;
+            value.round().clamp(0.0, 255.0) as u8
+        })
+    }
+
+    /// Terminal columns taken by one cell. A braille glyph's 2x4 dots and the two
+    /// halves of a half block are already square, a plain pixel takes `pixel_width`.
+    /// The glyph is repeated across all of them and the cursor moves by as many.
+    pub fn cell_columns(&self) -> u32 {
+        if self.braille || self.half_block {
+            1
+        } else {
+            self.pixel_width
+        }
+    }
+}
+
+/// Builds [`RenderOptions`] from the defaults the command line has, checking at
+/// [`build`](RenderOptionsBuilder::build) that the options go together.
+#[derive(Clone, Copy)]
+pub struct RenderOptionsBuilder {
+    options: RenderOptions,
+    /// `None` detects the mode when built
+    color: Option<ColorMode>,
+    /// `None` takes the cell aspect rounded to whole columns when built
+    pixel_width: Option<u32>,
+}
+
+impl Default for RenderOptionsBuilder {
+    fn default() -> Self {
+        RenderOptionsBuilder {
+            options: RenderOptions {
+                ramp: Ramp::Short,
+                color: ColorMode::Mono,
+                filter: Filter::Nearest,
+                sample: Sample::Point,
+                pixel_width: 2,
+                cell_aspect: 2.0,
+                braille: false,
+                half_block: false,
+                dither: false,
+                edges: false,
+                edge_threshold: None,
+                diff: false,
+                gamma: false,
+                luma: None,
+                lab: false,
+                color_levels: None,
+                posterize: None,
+                contrast: 1.0,
+                brightness: 0.0,
+                background: None,
+                invert: false,
+                block_rows: None,
+                graphics: None,
+                seed: 0,
+                crop: None,
+            },
+            color: None,
+            pixel_width: None,
+        }
+    }
+}
+
+impl RenderOptionsBuilder {
+    /// Glyphs luminance is mapped onto, [`Ramp::Short`] by default.
+    pub fn ramp(mut self, ramp: Ramp) -> Self {
+        self.options.ramp = ramp;
+        self
+    }
+
+    /// How colors are written, detected from the environment by default, see
+    /// [`ColorMode::detect`].
+    pub fn color_mode(mut self, color: ColorMode) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// How frames are scaled down, [`Filter::Nearest`] by default.
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.options.filter = filter;
+        self
+    }
+
+    /// How grid pixels are read from the source, [`Sample::Point`] by default.
+    pub fn sample(mut self, sample: Sample) -> Self {
+        self.options.sample = sample;
+        self
+    }
+
+    /// Terminal columns a plain pixel is drawn across, from 1 to 8. By default the
+    /// [`cell_aspect`](Self::cell_aspect) rounded, so 2.
+    pub fn pixel_width(mut self, columns: u32) -> Self {
+        self.pixel_width = Some(columns);
+        self
+    }
+
+    /// Height of the terminal's cells over their width, from 0.5 to 8. 2 by default.
+    pub fn cell_aspect(mut self, ratio: f32) -> Self {
+        self.options.cell_aspect = ratio;
+        self
+    }
+
+    /// Draws 2x4 pixel blocks as braille glyphs.
+    pub fn braille(mut self, braille: bool) -> Self {
+        self.options.braille = braille;
+        self
+    }
+
+    /// Draws two pixels per cell as a half block.
+    pub fn half_block(mut self, half_block: bool) -> Self {
+        self.options.half_block = half_block;
+        self
+    }
+
+    /// Dithers the glyphs of plain pixels.
+    pub fn dither(mut self, dither: bool) -> Self {
+        self.options.dither = dither;
+        self
+    }
+
+    /// Draws the outlines of plain pixels instead of shading them.
+    pub fn edges(mut self, edges: bool) -> Self {
+        self.options.edges = edges;
+        self
+    }
+
+    /// Shades with the ramp, with line glyphs along the edges at least `threshold`
+    /// strong, from 0 for the faintest to 1 for a step from black to white.
+    pub fn edge_threshold(mut self, threshold: f32) -> Self {
+        self.options.edge_threshold = Some(threshold);
+        self
+    }
+
+    /// Only draws the cells that changed since the previous frame.
+    pub fn diff(mut self, diff: bool) -> Self {
+        self.options.diff = diff;
+        self
+    }
+
+    /// Picks glyphs by the luminance of linear light.
+    pub fn gamma(mut self, gamma: bool) -> Self {
+        self.options.gamma = gamma;
+        self
+    }
+
+    /// Weights luminance is computed with, Rec. 601 for the sRGB bytes and Rec. 709
+    /// for linear light by default.
+    pub fn luma(mut self, luma: Luma) -> Self {
+        self.options.luma = Some(luma);
+        self
+    }
+
+    /// Matches palette colors by their CIELAB difference.
+    pub fn lab(mut self, lab: bool) -> Self {
+        self.options.lab = lab;
+        self
+    }
+
+    /// Rounds each channel of the cell colors to `levels` levels, at least 2.
+    pub fn color_levels(mut self, levels: u8) -> Self {
+        self.options.color_levels = Some(levels);
+        self
+    }
+
+    /// Keeps `bits` bits of each channel of every pixel, from 1 to 8.
+    pub fn posterize(mut self, bits: u8) -> Self {
+        self.options.posterize = Some(bits);
+        self
+    }
+
+    /// Stretches colors away from mid-gray by `contrast`, at least 0. 1 by default.
+    pub fn contrast(mut self, contrast: f32) -> Self {
+        self.options.contrast = contrast;
+        self
+    }
+
+    /// Brightens colors by a fraction of full brightness, from -1 to 1. 0 by default.
+    pub fn brightness(mut self, brightness: f32) -> Self {
+        self.options.brightness = brightness;
+        self
+    }
+
+    /// Composites transparent pixels over `color` rather than leaving them out.
+    pub fn background(mut self, color: [u8; 3]) -> Self {
+        self.options.background = Some(color);
+        self
+    }
+
+    /// Flips the ramp for light terminal themes.
+    pub fn invert(mut self, invert: bool) -> Self {
+        self.options.invert = invert;
+        self
+    }
+
+    /// Rows converted at a time by each thread, at least 1.
+    pub fn block_rows(mut self, rows: u32) -> Self {
+        self.options.block_rows = Some(rows);
+        self
+    }
+
+    /// Sends frames as pictures through `graphics` rather than as glyphs.
+    pub fn graphics(mut self, graphics: Graphics) -> Self {
+        self.options.graphics = Some(graphics);
+        self
+    }
+
+    /// Seeds whatever conversion picks at random. 0 by default.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.options.seed = seed;
+        self
+    }
+
+    /// Converts only the `width`x`height` pixels at `x`, `y` of each frame.
+    pub fn crop(mut self, (x, y, width, height): (u32, u32, u32, u32)) -> Self {
+        self.options.crop = Some((x, y, width, height));
+        self
+    }
+
+    /// The options, or what's wrong with them: values out of range, and glyph styles
+    /// that don't go together, the same ones the command line rejects.
+    pub fn build(self) -> anyhow::Result<RenderOptions> {
+        let options = RenderOptions {
+            color: self.color.unwrap_or_else(ColorMode::detect),
+            // Each pixel as close to square as whole columns get, the stretch makes up
+            // the rest. Braille and half block cells are a column wide as they are
+            pixel_width: self.pixel_width.unwrap_or(
+                match self.options.braille || self.options.half_block {
+                    true => 2,
+                    false => (self.options.cell_aspect.round() as u32).clamp(1, 8),
+                },
+            ),
+            ..self.options
+        };
+        let styles = [
+            ("braille", options.braille),
+            ("half block", options.half_block),
+            ("edges", options.edges),
+            ("edges over the ramp", options.edge_threshold.is_some()),
+        ];
+        let chosen = styles
+            .iter()
+            .filter(|(_, chosen)| *chosen)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        if let [first, second, ..] = chosen[..] {
+            bail!("{} and {} can't be drawn at once", first, second);
+        }
+        if options.dither && !chosen.is_empty() {
+            bail!("{} glyphs can't be dithered", chosen[0]);
+        }
+        if !(1..=8).contains(&options.pixel_width) {
+            bail!(
+                "pixel width must be from 1 to 8, not {}",
+                options.pixel_width
+            );
+        }
+        if options.pixel_width != 2 && (options.braille || options.half_block) {
+            bail!("braille and half block cells are one column wide, pixel width doesn't apply");
+        }
+        if !(0.5..=8.0).contains(&options.cell_aspect) {
+            bail!(
+                "cell aspect must be from 0.5 to 8, not {}",
+                options.cell_aspect
+            );
+        }
+        if !(options.contrast >= 0.0 && options.contrast.is_finite()) {
+            bail!("contrast must be at least 0, not {}", options.contrast);
+        }
+        if let Some(threshold) = options
+            .edge_threshold
+            .filter(|threshold| !(*threshold > 0.0 && *threshold <= 1.0))
+        {
+            bail!(
+                "edge threshold must be above 0 and at most 1, not {}",
+                threshold
+            );
+        }
+        if !(-1.0..=1.0).contains(&options.brightness) {
+            bail!(
+                "brightness must be from -1 to 1, not {}",
+                options.brightness
+            );
+        }
+        if options.color_levels.is_some_and(|levels| levels < 2) {
+            bail!("colors need at least 2 levels per channel");
+        }
+        if let Some(bits) = options.posterize.filter(|bits| !(1..=8).contains(bits)) {
+            bail!("posterize keeps from 1 to 8 bits per channel, not {}", bits);
+        }
+        if options
+            .crop
+            .is_some_and(|(_, _, width, height)| width == 0 || height == 0)
+        {
+            bail!("the crop needs to be at least a pixel wide and high");
+        }
+        if options.block_rows == Some(0) {
+            bail!("blocks need at least one row");
+        }
+        if let Ramp::Custom([]) = options.ramp {
+            bail!("the ramp needs at least one glyph");
+        }
+        Ok(options)
+    }
+}
+
+/// What one terminal cell shows.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Cell {
+    pub(crate) glyph: char,
+    /// `None` leaves the terminal's default color, for transparent pixels
+    pub(crate) color: Option<[u8; 3]>,
+    /// Only drawn in half-block mode
+    pub(crate) background: Option<[u8; 3]>,
+}
+
+/// Source pixel behind pixel (`x`, `y`) of a `width`x`height` grid over `img`, with
+/// the contrast, brightness and posterizing of `options` applied. A pixel that's
+/// transparent, even partly, is composited over the background of `options` by its
+/// alpha before the glyph and color are picked from it. Without a background, the
+/// pixels less than half opaque are `None` and the rest are taken as they are. The
+/// grid can be any size, a source smaller than it repeats its pixels and an empty
+/// one is all transparent.
+pub(crate) fn sample(
+    img: &DynamicImage,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    options: &RenderOptions,
+) -> Option<[u8; 3]> {
+    if img.width() == 0 || img.height() == 0 {
+        return options.background;
+    }
+    // In 64 bits since the products overflow for very large sources, and clamped for
+    // grid pixels past the last row or column
+    let source = |grid: u32, grid_size: u32, size: u32| {
+        ((grid as u64 * size as u64 / grid_size.max(1) as u64) as u32).min(size - 1)
+    };
+    let [r, g, b, a] = match options.sample {
+        Sample::Point => {
+            img.get_pixel(
+                source(x, width, img.width()),
+                source(y, height, img.height()),
+            )
+            .0
+        }
+        Sample::Average => {
+            // Source pixels up to where the next grid pixel starts, at least one
+            let span = |grid: u32, grid_size: u32, size: u32| {
+                let start = source(grid, grid_size, size);
+                let end = (grid as u64 + 1) * size as u64 / grid_size.max(1) as u64;
+                start..(end.min(size as u64) as u32).max(start + 1)
+            };
+            average(
+                img,
+                span(x, width, img.width()),
+                span(y, height, img.height()),
+            )
+        }
+    };
+    let pixel = match options.background {
+        Some(_) if a == 255 => [r, g, b],
+        Some(background) => {
+            let alpha = a as u32;
+            let mut pixel = [r, g, b];
+            for (channel, background) in pixel.iter_mut().zip(background) {
+                *channel = ((*channel as u32 * alpha + background as u32 * (255 - alpha) + 127)
+                    / 255) as u8;
+            }
+            pixel
+        }
+        None if a < 128 => return None,
+        None => [r, g, b],
+    };
+    Some(options.posterize(options.adjust(pixel)))
+}
+
+/// Mean of the pixels of `img` within `xs` and `ys`. The colors are weighed by their
+/// alpha like in [`Filter::resize`], so transparent pixels only make it more
+/// transparent.
+fn average(img: &DynamicImage, xs: Range<u32>, ys: Range<u32>) -> [u8; 4] {
+    let (mut sums, mut alpha, mut count) = ([0u64; 3], 0u64, 0u64);
+    for y in ys {
+        for x in xs.clone() {
+            let [r, g, b, a] = img.get_pixel(x, y).0;
+            for (sum, channel) in sums.iter_mut().zip([r, g, b]) {
+                *sum += channel as u64 * a as u64;
+            }
+            alpha += a as u64;
+            count += 1;
+        }
+    }
+    let [r, g, b] = sums.map(|sum| (sum + alpha / 2).checked_div(alpha).unwrap_or(0) as u8);
+    [r, g, b, ((alpha + count / 2) / count.max(1)) as u8]
+}
+
+// Bit of each braille dot, indexed by row then column of the 2x4 grid
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// Braille glyph for a 2x4 block of pixels, raising the dot of every pixel brighter
+/// than mid-gray. A black block is `U+2800` and a white one `U+28FF`, transparent
+/// pixels leave their dot down.
+fn braille_glyph(block: &[[Option<[u8; 3]>; 2]; 4], options: &RenderOptions) -> char {
+    let mut bits = 0;
+    for (row, dots) in block.iter().zip(BRAILLE_DOTS) {
+        for (&pixel, dot) in row.iter().zip(dots) {
+            if pixel.is_some_and(|pixel| options.luminance(pixel) >= 128.0) {
+                bits |= dot;
+            }
+        }
+    }
+    char::from_u32(0x2800 + bits).unwrap()
+}
+
+// Gradient strength a step from black to white gives
+const EDGE_STEP: f32 = 1020.0;
+
+// Gradient strength below which a pixel isn't part of an edge with --edges
+const EDGE_THRESHOLD: f32 = 160.0;
+
+/// Line glyph following the edge through pixel (`x`, `y`), found with a Sobel filter
+/// over the luminance of its neighbours, or `None` when there is no edge at least
+/// `threshold` strong. The edge runs across the gradient, so a change from top to
+/// bottom draws `-`.
+fn edge_glyph(
+    img: &DynamicImage,
+    (x, y): (u32, u32),
+    cols: u32,
+    rows: u32,
+    threshold: f32,
+    options: &RenderOptions,
+) -> Option<char> {
+    let luma = |dx: i32, dy: i32| {
+        let x = (x as i32 + dx).clamp(0, cols as i32 - 1) as u32;
+        let y = (y as i32 + dy).clamp(0, rows as i32 - 1) as u32;
+        sample(img, x, y, cols, rows, options).map_or(0.0, |pixel| options.luminance(pixel))
+    };
+    let [nw, n, ne, w, e, sw, s, se] = [
+        luma(-1, -1),
+        luma(0, -1),
+        luma(1, -1),
+        luma(-1, 0),
+        luma(1, 0),
+        luma(-1, 1),
+        luma(0, 1),
+        luma(1, 1),
+    ];
+    let gx = (ne + 2.0 * e + se) - (nw + 2.0 * w + sw);
+    let gy = (sw + 2.0 * s + se) - (nw + 2.0 * n + ne);
+    if gx.hypot(gy) < threshold {
+        return None;
+    }
+    // Direction of the gradient folded into half a turn, y pointing down
+    let angle = gy.atan2(gx).to_degrees().rem_euclid(180.0);
+    Some(match angle {
+        a if !(22.5..157.5).contains(&a) => '|',
+        a if a < 67.5 => '/',
+        a if a < 112.5 => '-',
+        _ => '\\',
+    })
+}
+
+fn sample_cell(
+    img: &DynamicImage,
+    x: u32,
+    y: u32,
+    cols: u32,
+    rows: u32,
+    options: &RenderOptions,
+) -> Cell {
+    if options.half_block {
+        let top = sample(img, x, y * 2, cols, rows * 2, options);
+        let bottom = sample(img, x, y * 2 + 1, cols, rows * 2, options);
+        if let ColorMode::Mono = options.color {
+            // Without colors the halves can only be on or off
+            let [top, bottom] = [top, bottom]
+                .map(|pixel| pixel.is_some_and(|pixel| options.luminance(pixel) >= 128.0));
+            let glyph = match (top, bottom) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            };
+            return Cell {
+                glyph,
+                color: None,
+                background: None,
+            };
+        }
+        // A transparent half shows the terminal's own background
+        return match (top, bottom) {
+            (Some(_), _) => Cell {
+                glyph: '▀',
+                color: top,
+                background: bottom,
+            },
+            (None, Some(_)) => Cell {
+                glyph: '▄',
+                color: bottom,
+                background: None,
+            },
+            (None, None) => Cell {
+                glyph: ' ',
+                color: None,
+                background: None,
+            },
+        };
+    }
+    if options.edges {
+        let color = sample(img, x, y, cols, rows, options);
+        return Cell {
+            glyph: edge_glyph(img, (x, y), cols, rows, EDGE_THRESHOLD, options).unwrap_or(' '),
+            color,
+            background: None,
+        };
+    }
+    if !options.braille {
+        let color = sample(img, x, y, cols, rows, options);
+        // Edges strong enough get a line glyph, everything else its shade
+        let edge = options
+            .edge_threshold
+            .filter(|_| color.is_some())
+            .and_then(|threshold| {
+                edge_glyph(img, (x, y), cols, rows, threshold * EDGE_STEP, options)
+            });
+        return Cell {
+            glyph: edge.unwrap_or_else(|| {
+                color.map_or(' ', |color| options.ramp.glyph(options.luminance(color)))
+            }),
+            color,
+            background: None,
+        };
+    }
+    let mut block = [[None; 2]; 4];
+    let mut sum = [0u32; 3];
+    let mut opaque = 0;
+    for (dy, row) in block.iter_mut().enumerate() {
+        for (dx, pixel) in row.iter_mut().enumerate() {
+            *pixel = sample(
+                img,
+                x * 2 + dx as u32,
+                y * 4 + dy as u32,
+                cols * 2,
+                rows * 4,
+                options,
+            );
+            if let Some(pixel) = *pixel {
+                for (sum, channel) in sum.iter_mut().zip(pixel) {
+                    *sum += channel as u32;
+                }
+                opaque += 1;
+            }
+        }
+    }
+    Cell {
+        glyph: braille_glyph(&block, options),
+        color: (opaque > 0).then(|| sum.map(|sum| (sum / opaque) as u8)),
+        background: None,
+    }
+}
+
+fn write_color(buf: &mut Vec<u8>, cell: Cell, options: &RenderOptions) {
+    match (options.color, cell.color) {
+        (ColorMode::Mono, _) => return,
+        (ColorMode::Truecolor, Some([r, g, b])) => {
+            write!(buf, "\x1b[38;2;{};{};{}m", r, g, b).unwrap()
+        }
+        (ColorMode::Ansi256, Some([r, g, b])) => {
+            write!(buf, "\x1b[38;5;{}m", ansi256(r, g, b, options.lab)).unwrap()
+        }
+        (_, None) => buf.extend_from_slice(b"\x1b[39m"),
+    }
+    if !options.half_block {
+        return;
+    }
+    match (options.color, cell.background) {
+        (ColorMode::Truecolor, Some([r, g, b])) => {
+            write!(buf, "\x1b[48;2;{};{};{}m", r, g, b).unwrap()
+        }
+        (ColorMode::Ansi256, Some([r, g, b])) => {
+            write!(buf, "\x1b[48;5;{}m", ansi256(r, g, b, options.lab)).unwrap()
+        }
+        _ => buf.extend_from_slice(b"\x1b[49m"),
+    }
+}
+
+/// Columns `glyph` takes on screen, `None` for control characters and the like that
+/// don't advance the cursor predictably.
+pub(crate) fn glyph_width(glyph: char) -> Option<u32> {
+    match glyph.width() {
+        Some(0) | None => None,
+        Some(width) => Some(width as u32),
+    }
+}
+
+/// Fills the cell's columns with its glyph, as many times as it fits. Whatever a
+/// wide glyph leaves over is padded with spaces, so the next cell still starts where
+/// the cursor math expects it.
+fn write_glyph(buf: &mut Vec<u8>, cell: Cell, options: &RenderOptions) {
+    let columns = options.cell_columns();
+    let width = glyph_width(cell.glyph).filter(|&width| width <= columns);
+    let repeats = width.map_or(0, |width| columns / width);
+    for _ in 0..repeats {
+        write!(buf, "{}", cell.glyph).unwrap();
+    }
+    for _ in repeats * width.unwrap_or(0)..columns {
+        buf.push(b' ');
+    }
+}
+
+/// Cursor position the terminal is left at after the cells written so far, and the
+/// last cell written for its colors, so runs of changed cells don't repeat what's
+/// already in effect.
+#[derive(Default)]
+struct Cursor {
+    at: Option<(u32, u32)>,
+    last: Option<Cell>,
+}
+
+fn write_cell(
+    buf: &mut Vec<u8>,
+    cursor: &mut Cursor,
+    (x, y): (u32, u32),
+    (origin_x, origin_y): (u32, u32),
+    cell: Cell,
+    options: &RenderOptions,
+) {
+    // A cell right after the last one written is already under the cursor. Cursor
+    // positions count from 1
+    match cursor.at {
+        Some(at) if at == (x, y) => {}
+        // Going on at the start of the next row takes moving to the next line, which
+        // is shorter than a position. It stays on the bottom line rather than scrolling
+        Some((_, row)) if x == 0 && origin_x == 0 && row + 1 == y => {
+            buf.extend_from_slice(b"\x1b[E")
+        }
+        _ => {
+            let column = origin_x + x * options.cell_columns() + 1;
+            write!(buf, "\x1b[{};{}H", origin_y + y + 1, column).unwrap();
+        }
+    }
+    if cursor
+        .last
+        .is_none_or(|last| (last.color, last.background) != (cell.color, cell.background))
+    {
+        write_color(buf, cell, options);
+    }
+    cursor.last = Some(cell);
+    write_glyph(buf, cell, options);
+    cursor.at = Some((x + 1, y));
+}
+
+/// Sets the colors and every other attribute back to the terminal's defaults.
+const RESET: &[u8] = b"\x1b[0m";
+
+/// Writes the cells of `frame` as plain lines of text rather than positioned cells,
+/// so the output can be viewed with `cat` or pasted as it is. Colors are only written
+/// when they change and reset at the end of each line.
+pub fn write_lines(buf: &mut Vec<u8>, frame: &FrameData, options: &RenderOptions) {
+    for row in frame.cells.chunks(frame.width as usize) {
+        let mut color = None;
+        for &cell in row {
+            if color != Some((cell.color, cell.background)) {
+                write_color(buf, cell, options);
+                color = Some((cell.color, cell.background));
+            }
+            write_glyph(buf, cell, options);
+        }
+        if !matches!(options.color, ColorMode::Mono) {
+            buf.extend_from_slice(RESET);
+        }
+        buf.push(b'\n');
+    }
+}
+
+/// Largest size with the source aspect ratio that fits within `max` pixels, a pixel
+/// at least, which is all an empty source gets.
+fn fit_to_terminal((width, height): (u32, u32), (max_width, max_height): (u32, u32)) -> (u32, u32) {
+    if width == 0 || height == 0 {
+        return (1, 1);
+    }
+    let factor = (max_width as f64 / width as f64).min(max_height as f64 / height as f64);
+    (
+        ((width as f64 * factor) as u32).max(1),
+        ((height as f64 * factor) as u32).max(1),
+    )
+}
+
+/// Largest frame with the source aspect ratio that fits a `cols`x`rows` terminal,
+/// in cells. A terminal too small for a single cell, or reported as `0`x`0` while
+/// it's resized, still gets one.
+pub fn fit_cells(
+    source: (u32, u32),
+    (cols, rows): (u16, u16),
+    options: &RenderOptions,
+) -> (u32, u32) {
+    let source = options.cell_shape(source);
+    let (cell_width, cell_height) = options.cell_pixels();
+    let max_width = (cols as u32 / options.cell_columns()).max(1) * cell_width;
+    let max_height = (rows as u32).max(1) * cell_height;
+    let (width, height) = fit_to_terminal(source, (max_width, max_height));
+    (width.div_ceil(cell_width), height.div_ceil(cell_height))
+}
+
+/// `img` as a `width`x`height` picture: scaled to fit within it with its own aspect
+/// ratio and centered, with transparent bars filling the rest. For sequences whose
+/// frames vary in size, which would stretch to the shape of the first otherwise. A
+/// frame already of that size is left as it is.
+pub fn letterbox(
+    img: &DynamicImage,
+    (width, height): (u32, u32),
+    filter: Filter,
+) -> Cow<'_, DynamicImage> {
+    if img.dimensions() == (width, height) {
+        return Cow::Borrowed(img);
+    }
+    let (inner_width, inner_height) = fit_to_terminal(img.dimensions(), (width, height));
+    let scaled = filter.resize(img, inner_width, inner_height);
+    let mut canvas = image::RgbaImage::new(width, height);
+    image::imageops::overlay(
+        &mut canvas,
+        &scaled.to_rgba8(),
+        ((width - inner_width) / 2) as i64,
+        ((height - inner_height) / 2) as i64,
+    );
+    Cow::Owned(DynamicImage::ImageRgba8(canvas))
+}
+
+fn luminance(r: u8, g: u8, b: u8, luma: Luma) -> f32 {
+    let [wr, wg, wb] = luma.weights();
+    wr * r as f32 + wg * g as f32 + wb * b as f32
+}
+
+/// Linear light of each sRGB byte value, from 0 to 1.
+static SRGB_TO_LINEAR: LazyLock<[f32; 256]> = LazyLock::new(|| {
+    std::array::from_fn(|value| {
+        let value = value as f32 / 255.0;
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    })
+});
+
+/// Luminance computed in linear light, then encoded back to sRGB so it still maps
+/// evenly onto the ramp.
+fn linear_luminance(r: u8, g: u8, b: u8, luma: Luma) -> f32 {
+    let [wr, wg, wb] = luma.weights();
+    let linear = wr * SRGB_TO_LINEAR[r as usize]
+        + wg * SRGB_TO_LINEAR[g as usize]
+        + wb * SRGB_TO_LINEAR[b as usize];
+    let encoded = if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    encoded * 255.0
+}
+
+/// One converted frame, in cells.
+pub struct FrameData {
+    /// Size of the frame in cells
+    pub width: u32,
+    pub height: u32,
+    /// Position of the frame in its source
+    pub index: usize,
+    /// Seek generation the frame was produced in, left to the player
+    pub epoch: u64,
+    /// How long the frame stays on screen, `--fps` decides when the source doesn't
+    pub delay: Option<Duration>,
+    /// Clear the screen before drawing, set when the frame size changed
+    pub clear: bool,
+    /// Terminal column and row the top left cell is drawn at, counting from 0
+    pub origin: (u32, u32),
+    /// What each cell shows, row by row
+    pub cells: Vec<Cell>,
+    /// Escape sequences drawing the cells at their positions on screen
+    pub data: Vec<u8>,
+    /// Escapes of each block of rows after the first while it's converted, kept for
+    /// the next frame to write into
+    blocks: Vec<Vec<u8>>,
+}
+
+/// Converts `img` into the cells of `frame` and the escape sequence stream that draws
+/// them. Given the cells already on screen as `previous`, only changed cells are drawn.
+pub fn render_frame(
+    img: &DynamicImage,
+    frame: &mut FrameData,
+    previous: Option<&[Cell]>,
+    options: &RenderOptions,
+) {
+    let cropped;
+    let img = match options.crop {
+        Some((x, y, width, height)) => {
+            cropped = img.crop_imm(x, y, width, height);
+            &cropped
+        }
+        None => img,
+    };
+    if let Some(graphics) = options.graphics {
+        return render_graphics(img, frame, graphics, options);
+    }
+    let cols = frame.width;
+    let rows = frame.height;
+    // Resample to exactly one source pixel per frame pixel up front, sampling then
+    // reads pixels one to one. Nearest sampling needs no copy.
+    let (cell_width, cell_height) = options.cell_pixels();
+    let resized;
+    let img = match options.filter {
+        Filter::Nearest => img,
+        filter => {
+            resized = filter.resize(img, cols * cell_width, rows * cell_height);
+            &resized
+        }
+    };
+    frame.data.clear();
+    if cols == 0 || rows == 0 {
+        frame.cells.clear();
+        return;
+    }
+    // One block of rows per thread of the rayon pool, or blocks of `block_rows` rows
+    // each, the last taking what's left. Each block writes its cells straight into
+    // its own part of the frame's cells, and its escapes into a buffer of its own
+    // kept in the frame, the first block's being the frame's own. Once they've grown
+    // to fit, converting a frame allocates nothing
+    let block_height = match options.block_rows {
+        Some(block_rows) => block_rows.clamp(1, rows),
+        None => rows.div_ceil(rayon::current_num_threads() as u32),
+    };
+    let block_count = rows.div_ceil(block_height) as usize;
+    let blank = Cell {
+        glyph: ' ',
+        color: None,
+        background: None,
+    };
+    frame.cells.resize((cols * rows) as usize, blank);
+    frame.blocks.resize_with(block_count - 1, Vec::new);
+    let origin = frame.origin;
+    let buffers = rayon::iter::once(&mut frame.data).chain(frame.blocks.par_iter_mut());
+    frame
+        .cells
+        .par_chunks_mut((block_height * cols) as usize)
+        .zip(buffers)
+        .enumerate()
+        .for_each(|(block_id, (cells, buf))| {
+            buf.clear();
+            // The frame starts from the default colors, whatever was set before it
+            if block_id == 0 {
+                buf.extend_from_slice(RESET);
+            }
+            let start = block_id as u32 * block_height;
+            // Luminance error carried into this row and the next, padded by a cell on
+            // either side. Error doesn't cross into the next block.
+            let dither_row = || match options.dither {
+                true => vec![0.0; cols as usize + 2],
+                false => Vec::new(),
+            };
+            let (mut error, mut next_error) = (dither_row(), dither_row());
+            // Each block is written on its own, so it starts from an unknown cursor
+            let mut cursor = Cursor::default();
+            for (row, y) in cells.chunks_mut(cols as usize).zip(start..) {
+                for (slot, x) in row.iter_mut().zip(0..) {
+                    let mut cell = sample_cell(img, x, y, cols, rows, options);
+                    if let (true, Some(color)) = (options.dither, cell.color) {
+                        let i = x as usize + 1;
+                        let luma = options.luminance(color) + error[i];
+                        let (glyph, quantized) = options.ramp.quantize(luma);
+                        cell.glyph = glyph;
+                        let diff = luma - quantized;
+                        error[i + 1] += diff * 7.0 / 16.0;
+                        next_error[i - 1] += diff * 3.0 / 16.0;
+                        next_error[i] += diff * 5.0 / 16.0;
+                        next_error[i + 1] += diff / 16.0;
+                    }
+                    // After the glyph is picked, which goes by the exact color
+                    cell.color = cell.color.map(|color| options.quantize(color));
+                    cell.background = cell.background.map(|color| options.quantize(color));
+                    let index = (y * cols + x) as usize;
+                    if previous.is_none_or(|previous| previous[index] != cell) {
+                        write_cell(buf, &mut cursor, (x, y), origin, cell, options);
+                    }
+                    *slot = cell;
+                }
+                std::mem::swap(&mut error, &mut next_error);
+                next_error.fill(0.0);
+            }
+        });
+    for block in &frame.blocks {
+        frame.data.extend_from_slice(block);
+    }
+    // And leaves them that way for what comes after it. A frame that changes no cell
+    // writes nothing at all
+    if frame.data.len() == RESET.len() {
+        frame.data.clear();
+    } else {
+        frame.data.extend_from_slice(RESET);
+    }
+}
+
+/// Lays `tiles`, frames of one size, out left to right and top to bottom on a grid
+/// of `columns`x`rows` of them, with a blank column and row between each. Places
+/// past the last tile are left blank. A sheet with more cells than a frame holds
+/// is an error.
+pub fn montage(tiles: &[FrameData], (columns, rows): (u32, u32)) -> anyhow::Result<FrameData> {
+    let (tile_width, tile_height) = tiles
+        .first()
+        .map_or((1, 1), |tile| (tile.width, tile.height));
+    let side = |count: u32, tile: u32| Some(count.checked_mul(tile.checked_add(1)?)? - 1);
+    let Some((width, height)) = side(columns, tile_width)
+        .zip(side(rows, tile_height))
+        .filter(|(width, height)| width.checked_mul(*height).is_some())
+    else {
+        bail!(
+            "a contact sheet of {}x{} frames of {}x{} cells is too large",
+            columns,
+            rows,
+            tile_width,
+            tile_height
+        );
+    };
+    let mut sheet = FrameData::new(width, height);
+    let blank = Cell {
+        glyph: ' ',
+        color: None,
+        background: None,
+    };
+    sheet.cells.resize((width * height) as usize, blank);
+    for (i, tile) in tiles
+        .iter()
+        .take(columns as usize * rows as usize)
+        .enumerate()
+    {
+        let x = i as u32 % columns * (tile_width + 1);
+        let y = i as u32 / columns * (tile_height + 1);
+        for (row, cells) in tile.cells.chunks(tile_width as usize).enumerate() {
+            let start = ((y + row as u32) * width + x) as usize;
+            sheet.cells[start..start + cells.len()].copy_from_slice(cells);
+        }
+    }
+    Ok(sheet)
+}
+
+impl FrameData {
+    /// Empty `width`x`height` frame with room for its cells and escapes.
+    pub fn new(width: u32, height: u32) -> Self {
+        FrameData {
+            width,
+            height,
+            index: 0,
+            epoch: 0,
+            delay: None,
+            clear: false,
+            origin: (0, 0),
+            cells: Vec::with_capacity(width as usize * height as usize),
+            data: Vec::with_capacity(width as usize * height as usize * 20),
+            blocks: Vec::new(),
+        }
+    }
+}
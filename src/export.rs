@@ -0,0 +1,171 @@
+//! Drawing converted frames back into pictures, so the result can be shared as an
+//! image rather than as escape sequences.
+
+use ab_glyph::{point, Font, FontRef, PxScale, PxScaleFont, ScaleFont};
+use image::{Rgb, RgbImage};
+
+use crate::convert::{glyph_width, ColorMode, FrameData, RenderOptions};
+
+/// DejaVu Sans Mono, which covers the ramps and the block elements. It has no
+/// braille, those are drawn as dots of their own.
+const DEFAULT_FONT: &[u8] = include_bytes!("../assets/DejaVuSansMono.ttf");
+
+/// Colors a terminal with a dark theme shows for cells that leave them unset.
+const FOREGROUND: [u8; 3] = [204, 204, 204];
+const BACKGROUND: [u8; 3] = [0, 0, 0];
+
+/// The monospace font bundled with the crate.
+pub fn default_font() -> FontRef<'static> {
+    FontRef::try_from_slice(DEFAULT_FONT).expect("the bundled font is valid")
+}
+
+/// Draws `frame` the way a terminal would show it, in `font` at `size` pixels. Each
+/// terminal column is as wide as the font's advance and each row as tall as its
+/// lines, so a cell takes `options.cell_columns()` of those columns.
+pub fn rasterize<F: Font>(
+    frame: &FrameData,
+    options: &RenderOptions,
+    font: &F,
+    size: f32,
+) -> RgbImage {
+    let font = font.as_scaled(PxScale::from(size));
+    let (column_width, line_height) = metrics(&font);
+    let columns = options.cell_columns();
+    let cell_width = column_width * columns;
+    let mut img = RgbImage::new(frame.width * cell_width, frame.height * line_height);
+    for (i, &cell) in frame.cells.iter().enumerate() {
+        let x = i as u32 % frame.width * cell_width;
+        let y = i as u32 / frame.width * line_height;
+        // Mono output leaves the colors to the terminal
+        let (foreground, background) = match options.color {
+            ColorMode::Mono => (FOREGROUND, BACKGROUND),
+            _ => (
+                cell.color.unwrap_or(FOREGROUND),
+                cell.background.unwrap_or(BACKGROUND),
+            ),
+        };
+        fill(&mut img, (x, y), (cell_width, line_height), background);
+        // Repeated across the cell's columns like in the terminal
+        let Some(width) = glyph_width(cell.glyph).filter(|&width| width <= columns) else {
+            continue;
+        };
+        for repeat in 0..columns / width {
+            let origin = (x + repeat * width * column_width, y);
+            let bounds = (width * column_width, line_height);
+            draw_glyph(&mut img, &font, cell.glyph, origin, bounds, foreground);
+        }
+    }
+    img
+}
+
+/// Writes `label` into the top left corner of `img`, in `font` at `size` pixels and
+/// on a box of the background color so it reads over any frame. Whatever doesn't
+/// fit on the image is cut off.
+pub fn burn_label<F: Font>(img: &mut RgbImage, label: &str, font: &F, size: f32) {
+    let font = font.as_scaled(PxScale::from(size));
+    let (column_width, line_height) = metrics(&font);
+    let width = (label.chars().count() as u32 * column_width).min(img.width());
+    let height = line_height.min(img.height());
+    fill(img, (0, 0), (width, height), BACKGROUND);
+    for (glyph, x) in label.chars().zip((0..width).step_by(column_width as usize)) {
+        let bounds = (column_width.min(width - x), height);
+        draw_glyph(img, &font, glyph, (x, 0), bounds, FOREGROUND);
+    }
+}
+
+/// Width of a terminal column in `font` and height of its lines, in pixels.
+fn metrics<F: Font>(font: &PxScaleFont<&F>) -> (u32, u32) {
+    (
+        (font.h_advance(font.glyph_id('M')).ceil() as u32).max(1),
+        (font.height().ceil() as u32).max(1),
+    )
+}
+
+fn fill(img: &mut RgbImage, (x, y): (u32, u32), (width, height): (u32, u32), color: [u8; 3]) {
+    for py in y..y + height {
+        for px in x..x + width {
+            img.put_pixel(px, py, Rgb(color));
+        }
+    }
+}
+
+/// Draws `glyph` within the `bounds` at `origin`, clipping whatever of it reaches
+/// past them.
+fn draw_glyph<F: Font>(
+    img: &mut RgbImage,
+    font: &PxScaleFont<&F>,
+    glyph: char,
+    (x, y): (u32, u32),
+    (width, height): (u32, u32),
+    color: [u8; 3],
+) {
+    // Block elements have to meet their neighbours exactly, which the font's own
+    // outlines don't promise
+    match glyph {
+        ' ' => return,
+        '█' => return fill(img, (x, y), (width, height), color),
+        '▀' => return fill(img, (x, y), (width, height / 2), color),
+        '▄' => {
+            return fill(
+                img,
+                (x, y + height / 2),
+                (width, height - height / 2),
+                color,
+            )
+        }
+        '\u{2800}'..='\u{28ff}' => return draw_braille(img, glyph, (x, y), (width, height), color),
+        _ => {}
+    }
+    let mut scaled = font.scaled_glyph(glyph);
+    scaled.position = point(x as f32, y as f32 + font.ascent());
+    let Some(outline) = font.outline_glyph(scaled) else {
+        return;
+    };
+    let bounds = outline.px_bounds();
+    outline.draw(|gx, gy, coverage| {
+        let px = bounds.min.x as i64 + gx as i64;
+        let py = bounds.min.y as i64 + gy as i64;
+        let inside = (x as i64..(x + width) as i64).contains(&px)
+            && (y as i64..(y + height) as i64).contains(&py);
+        if !inside {
+            return;
+        }
+        let pixel = img.get_pixel_mut(px as u32, py as u32);
+        for (channel, target) in pixel.0.iter_mut().zip(color) {
+            *channel =
+                (*channel as f32 + (target as f32 - *channel as f32) * coverage).round() as u8;
+        }
+    });
+}
+
+/// Draws the raised dots of braille `glyph` as discs on a 2x4 grid over the bounds.
+fn draw_braille(
+    img: &mut RgbImage,
+    glyph: char,
+    (x, y): (u32, u32),
+    (width, height): (u32, u32),
+    color: [u8; 3],
+) {
+    // Bit of each dot in the pattern, by column and row
+    const DOTS: [[u32; 4]; 2] = [[0, 1, 2, 6], [3, 4, 5, 7]];
+    let bits = glyph as u32 - 0x2800;
+    let (dot_width, dot_height) = (width as f32 / 2.0, height as f32 / 4.0);
+    let radius = dot_width.min(dot_height) * 0.35;
+    for (column, rows) in DOTS.iter().enumerate() {
+        for (row, bit) in rows.iter().enumerate() {
+            if bits & 1 << bit == 0 {
+                continue;
+            }
+            let center_x = x as f32 + (column as f32 + 0.5) * dot_width;
+            let center_y = y as f32 + (row as f32 + 0.5) * dot_height;
+            for py in y..y + height {
+                for px in x..x + width {
+                    let (dx, dy) = (px as f32 + 0.5 - center_x, py as f32 + 0.5 - center_y);
+                    if dx * dx + dy * dy <= radius * radius {
+                        img.put_pixel(px, py, Rgb(color));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Frames sent as pictures through a terminal graphics protocol rather than drawn
+//! with glyphs, for terminals that can show them.
+
+use std::io::{Cursor, Write};
+
+use clap::ValueEnum;
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+
+use crate::convert::{ansi256, palette_rgb, sample, FrameData, RenderOptions};
+
+/// Terminal graphics protocols frames can be sent through.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Protocol {
+    /// The kitty graphics protocol, also spoken by WezTerm, Ghostty and Konsole
+    Kitty,
+    /// iTerm2's inline images, also shown by WezTerm
+    Iterm,
+    /// DEC sixels, in the colors of the 256-color palette
+    Sixel,
+}
+
+impl Protocol {
+    /// Protocol the terminal running us is known to show, from `$TERM` and the
+    /// variables terminals set for their programs. Sixel support can't be told from
+    /// them reliably, it's only picked on terminals whose `$TERM` names it.
+    pub fn detect() -> Option<Self> {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        let (term, program) = (var("TERM"), var("TERM_PROGRAM"));
+        if term == "xterm-kitty"
+            || std::env::var_os("KITTY_WINDOW_ID").is_some()
+            || matches!(program.as_str(), "WezTerm" | "ghostty")
+        {
+            Some(Protocol::Kitty)
+        } else if program == "iTerm.app" {
+            Some(Protocol::Iterm)
+        } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+            Some(Protocol::Sixel)
+        } else {
+            None
+        }
+    }
+}
+
+/// A protocol along with the size pictures are sent at.
+#[derive(Clone, Copy)]
+pub struct Graphics {
+    pub protocol: Protocol,
+    /// Pixels of a terminal cell, across and down. Sixels are drawn at that size
+    /// exactly, the other protocols scale the picture to the cells it covers
+    pub cell_size: (u32, u32),
+}
+
+/// Sends `img` through the protocol of `graphics`, covering the cells `frame` would
+/// take with glyphs. The colors are adjusted and transparent pixels composited the
+/// same way as for glyphs. The frame is left without cells.
+pub(crate) fn render_graphics(
+    img: &DynamicImage,
+    frame: &mut FrameData,
+    graphics: Graphics,
+    options: &RenderOptions,
+) {
+    let columns = frame.width * options.cell_columns();
+    let (cell_width, cell_height) = graphics.cell_size;
+    let (width, height) = (columns * cell_width, frame.height * cell_height);
+    let resized = options.filter.resize(img, width, height);
+    let pixels = RgbaImage::from_fn(width, height, |x, y| {
+        match sample(&resized, x, y, width, height, options) {
+            Some([r, g, b]) => Rgba([r, g, b, 255]),
+            None => Rgba([0; 4]),
+        }
+    });
+    frame.cells.clear();
+    frame.data.clear();
+    let (x, y) = frame.origin;
+    write!(frame.data, "\x1b[{};{}H", y + 1, x + 1).unwrap();
+    match graphics.protocol {
+        Protocol::Kitty => kitty(&mut frame.data, &png(pixels), (columns, frame.height)),
+        Protocol::Iterm => iterm(&mut frame.data, &png(pixels), (columns, frame.height)),
+        Protocol::Sixel => sixel(&mut frame.data, &pixels, options),
+    }
+}
+
+fn png(pixels: RgbaImage) -> Vec<u8> {
+    let mut png = Vec::new();
+    DynamicImage::ImageRgba8(pixels)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .expect("encoding into memory doesn't fail");
+    png
+}
+
+/// Transmits `png` and places it over `columns`x`rows` cells, in chunks as the
+/// protocol asks for. Every frame takes the place of the one before under the same
+/// ids, and the terminal is asked not to answer, which would show up as key presses.
+fn kitty(buf: &mut Vec<u8>, png: &[u8], (columns, rows): (u32, u32)) {
+    let payload = base64(png);
+    let chunks = payload.chunks(4096).collect::<Vec<_>>();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        if i == 0 {
+            write!(
+                buf,
+                "\x1b_Ga=T,f=100,i=1,p=1,q=2,C=1,c={},r={},m={};",
+                columns, rows, more
+            )
+            .unwrap();
+        } else {
+            write!(buf, "\x1b_Gm={};", more).unwrap();
+        }
+        buf.extend_from_slice(chunk);
+        buf.extend_from_slice(b"\x1b\\");
+    }
+}
+
+/// Shows `png` inline over `columns`x`rows` cells, leaving the cursor where it is so
+/// a frame reaching the bottom doesn't scroll the screen.
+fn iterm(buf: &mut Vec<u8>, png: &[u8], (columns, rows): (u32, u32)) {
+    write!(
+        buf,
+        "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=0;doNotMoveCursor=1:",
+        png.len(),
+        columns,
+        rows
+    )
+    .unwrap();
+    buf.extend_from_slice(&base64(png));
+    buf.push(b'\x07');
+}
+
+/// Encodes `pixels` as sixels, in bands of six rows. Each color of the 256-color
+/// palette from 16 up gets a register, and each band is drawn once per color in it,
+/// with runs of the same column pattern compressed. Transparent pixels are left as
+/// they are on screen.
+fn sixel(buf: &mut Vec<u8>, pixels: &RgbaImage, options: &RenderOptions) {
+    let (width, height) = pixels.dimensions();
+    write!(buf, "\x1bP0;1;0q\"1;1;{};{}", width, height).unwrap();
+    for register in 0..240u8 {
+        let [r, g, b] = palette_rgb(16 + register).map(|channel| channel as u32 * 100 / 255);
+        write!(buf, "#{};2;{};{};{}", register, r, g, b).unwrap();
+    }
+    let register = |x, y| {
+        let Rgba([r, g, b, a]) = *pixels.get_pixel(x, y);
+        (a >= 128).then(|| ansi256(r, g, b, options.lab) - 16)
+    };
+    let mut band = vec![None; width as usize * 6];
+    let mut pattern = vec![0u8; width as usize];
+    for top in (0..height).step_by(6) {
+        let rows = (height - top).min(6);
+        for dy in 0..rows {
+            for x in 0..width {
+                band[(dy * width + x) as usize] = register(x, top + dy);
+            }
+        }
+        let mut used = [false; 240];
+        for &register in &band[..(rows * width) as usize] {
+            if let Some(register) = register {
+                used[register as usize] = true;
+            }
+        }
+        let mut first = true;
+        for color in (0..240u8).filter(|&color| used[color as usize]) {
+            for (x, bits) in pattern.iter_mut().enumerate() {
+                *bits = (0..rows)
+                    .filter(|&dy| band[dy as usize * width as usize + x] == Some(color))
+                    .fold(0, |bits, dy| bits | 1 << dy);
+            }
+            // Back to the start of the band to overlay the next color
+            if !first {
+                buf.push(b'$');
+            }
+            first = false;
+            write!(buf, "#{}", color).unwrap();
+            for run in pattern.chunk_by(|a, b| a == b) {
+                let glyph = 63 + run[0];
+                match run.len() {
+                    1..=3 => buf.extend(std::iter::repeat_n(glyph, run.len())),
+                    len => write!(buf, "!{}{}", len, glyph as char).unwrap(),
+                }
+            }
+        }
+        buf.push(b'-');
+    }
+    buf.extend_from_slice(b"\x1b\\");
+}
+
+fn base64(data: &[u8]) -> Vec<u8> {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = Vec::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            // A chunk of n bytes fills n + 1 of the 4 characters, `=` pads the rest
+            encoded.push(if i <= chunk.len() {
+                ALPHABET[(bits >> (18 - 6 * i) & 63) as usize]
+            } else {
+                b'='
+            });
+        }
+    }
+    encoded
+}
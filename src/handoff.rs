@@ -0,0 +1,72 @@
+//! How frame buffers go between the thread converting frames and the one showing
+//! them. They're handed over by value, so each has exactly one owner at a time: the
+//! converting side fills an empty one and hands it over, the showing side draws it
+//! and gives it back to be filled again.
+
+use std::{
+    sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender},
+    time::Duration,
+};
+
+use crate::convert::FrameData;
+
+/// The converting side of a [`handoff`].
+pub struct Producer {
+    empty: Receiver<FrameData>,
+    ready: SyncSender<FrameData>,
+}
+
+/// The showing side of a [`handoff`].
+pub struct Consumer {
+    ready: Receiver<FrameData>,
+    empty: SyncSender<FrameData>,
+}
+
+/// Sets up `lookahead` buffers of `width`x`height` cells for the producer to fill,
+/// besides the one the consumer has on screen. Neither side ever holds more than
+/// those, so handing one over never blocks.
+pub fn handoff(lookahead: usize, (width, height): (u32, u32)) -> (Producer, Consumer) {
+    let lookahead = lookahead.max(1);
+    let (ready_tx, ready_rx) = sync_channel(lookahead);
+    let (empty_tx, empty_rx) = sync_channel(lookahead);
+    for _ in 0..lookahead {
+        empty_tx
+            .send(FrameData::new(width, height))
+            .expect("the channel has room for every buffer");
+    }
+    let producer = Producer {
+        empty: empty_rx,
+        ready: ready_tx,
+    };
+    let consumer = Consumer {
+        ready: ready_rx,
+        empty: empty_tx,
+    };
+    (producer, consumer)
+}
+
+impl Producer {
+    /// Waits for a buffer to fill. `None` once the consumer has hung up.
+    pub fn next_buffer(&self) -> Option<FrameData> {
+        self.empty.recv().ok()
+    }
+
+    /// Hands a filled buffer to the consumer. `false` once it has hung up.
+    pub fn hand_over(&self, frame: FrameData) -> bool {
+        self.ready.send(frame).is_ok()
+    }
+}
+
+impl Consumer {
+    /// Waits up to `wait` for the next frame. The frames the producer handed over are
+    /// all taken before it counts as hung up.
+    pub fn next_frame(&self, wait: Duration) -> Result<FrameData, RecvTimeoutError> {
+        self.ready.recv_timeout(wait)
+    }
+
+    /// Gives a buffer back to the producer to fill. Once that has handed over the
+    /// last frame it's gone, and the buffer is dropped.
+    pub fn give_back(&self, frame: FrameData) {
+        let _ = self.empty.send(frame);
+    }
+}
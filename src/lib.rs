@@ -0,0 +1,43 @@
+//! Turns images, animated GIFs and WebPs, videos and raw frame streams into colored
+//! text for the terminal.
+//!
+//! [`open_source`] opens the frames to play, [`open_raw`] a stream of raw pixels
+//! written by another program, [`render_frame`] converts one of them
+//! into cells and the escape sequences that draw them, and [`render`] does both in
+//! one go for a single image. [`rasterize`] draws converted cells into a picture
+//! instead. [`RenderOptions::builder`] sets up the options they all take, and
+//! [`next_deadline`] keeps the frames to the clock when they play, and [`handoff`]
+//! passes them from the thread converting them to the one showing them.
+
+mod clock;
+mod convert;
+mod export;
+mod graphics;
+mod handoff;
+mod source;
+
+pub use clock::{frames_behind, next_deadline};
+pub use convert::{
+    crossfade, fit_cells, letterbox, montage, render_frame, rgb_to_lab, terminal_caps, write_lines,
+    Align, Cell, ColorMode, Filter, FrameData, Layout, Luma, Ramp, RenderOptions,
+    RenderOptionsBuilder, Sample,
+};
+pub use export::{burn_label, default_font, rasterize};
+pub use graphics::{Graphics, Protocol};
+pub use handoff::{handoff, Consumer, Producer};
+pub use source::{
+    open_playlist, open_raw, open_source, DecodeError, NoFrames, RawFormat, RawInfo, ReadAhead,
+    Source, Stream, Video, VIDEO_EXTENSIONS,
+};
+
+use image::DynamicImage;
+
+/// Converts `img` into `width`x`height` cells, as lines of text with the escapes
+/// `options` asks for, ready to be printed.
+pub fn render(img: &DynamicImage, width: u32, height: u32, options: &RenderOptions) -> Vec<u8> {
+    let mut frame = FrameData::new(width, height);
+    render_frame(img, &mut frame, None, options);
+    let mut buf = Vec::new();
+    write_lines(&mut buf, &frame, options);
+    buf
+}
@@ -1,218 +1,2240 @@
+mod audio;
+mod config;
+mod sink;
+mod stats;
+
 use std::{
-    io::Write,
-    path::PathBuf,
-    sync::{atomic::AtomicPtr, mpsc::channel, Arc},
+    borrow::Cow,
+    ffi::OsString,
+    fs::File,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::RecvTimeoutError,
+        Arc, Mutex,
+    },
     thread,
+    time::{Duration, Instant},
 };
 
+use ab_glyph::FontArc;
+use anyhow::{anyhow, bail, Context};
+use ascii_art::{
+    burn_label, crossfade, default_font, frames_behind, handoff, letterbox, montage, next_deadline,
+    open_playlist, open_raw, open_source, rasterize, render_frame, terminal_caps, write_lines,
+    Align, ColorMode, Consumer, DecodeError, Filter, FrameData, Graphics, Layout, Luma, NoFrames,
+    Producer, Protocol, Ramp, RawFormat, RawInfo, RenderOptions, Sample, Source, VIDEO_EXTENSIONS,
+};
+use clap::{
+    error::ErrorKind, parser::ValueSource, CommandFactory, FromArgMatches, Parser, ValueEnum,
+};
 use crossterm::{
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, LeaveAlternateScreen},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use image::GenericImageView;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use image::{DynamicImage, GenericImageView, ImageFormat};
 
-struct FrameData {
-    width: u32,
-    height: u32,
-    //data: Vec<(u8, u8, u8, char)>,
-    data: Vec<u8>,
-}
+use crate::{
+    audio::Audio,
+    config::{Config, CONFIG_FILE},
+    sink::{DirSink, FileSink, NullSink, OutputSink, TerminalSink},
+    stats::{Stage, Stats},
+};
 
-struct DoubleBuffer {
-    front: AtomicPtr<FrameData>,
-    back: AtomicPtr<FrameData>,
-    temp: AtomicPtr<FrameData>,
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Image file, animated GIF or WebP, video or directory of numbered frames. With
+    /// --raw or --raw-info, the file or pipe the frames are read from, `-` for stdin
+    #[arg(required_unless_present_any = ["playlist", "list_formats"])]
+    path: Option<PathBuf>,
+    /// Print what the terminal is found to support and the formats that can be
+    /// played, then exit, to find out why colors or pictures come out wrong
+    #[arg(long, conflicts_with_all = ["path", "playlist"])]
+    list_formats: bool,
+    /// Play the images listed in FILE in order instead, one path per line, relative to
+    /// the file. Blank lines and lines starting with `#` are passed over
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["path", "raw", "raw_info", "formats"])]
+    playlist: Option<PathBuf>,
+    /// Downscale factor applied to the source frames, fits the terminal when omitted
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    scale: Option<u32>,
+    /// Target playback frame rate, videos are also sampled at this rate
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u32).range(1..=120))]
+    fps: u32,
+    /// Play the sequence N times, 0 loops forever [default: 0, once for a single
+    /// image, an animation's own loop count]
+    #[arg(long = "loop", value_name = "N")]
+    loop_count: Option<u32>,
+    /// Play the sequence once, same as --loop 1
+    #[arg(long, conflicts_with = "loop_count")]
+    no_loop: bool,
+    /// Blend each frame into the next one instead of cutting, for slideshows of
+    /// images
+    #[arg(long, value_enum, conflicts_with_all = ["output", "export_image", "dump_dir"])]
+    transition: Option<Transition>,
+    /// How long a transition takes, whatever the frame rate
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 500,
+        requires = "transition"
+    )]
+    transition_ms: u64,
+    /// Show a frame identical to the one before it as that was converted instead of
+    /// converting it again, for videos with repeated frames. With --diff it draws
+    /// nothing at all
+    #[arg(long)]
+    dedupe: bool,
+    /// Start at frame N of the sequence, counting from 1 [default: the first]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), conflicts_with_all = ["raw", "raw_info"])]
+    start: Option<u64>,
+    /// Stop after frame N of the sequence, counting from 1 [default: the last]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), conflicts_with_all = ["raw", "raw_info"])]
+    end: Option<u64>,
+    /// Write the first frame, or the one --start picks, as text and exit, without
+    /// taking over the terminal
+    #[arg(long, conflicts_with_all = ["raw", "raw_info", "end", "loop_count", "no_loop", "transition", "uncapped", "audio"])]
+    once: bool,
+    /// Draw 2x4 pixel blocks as braille glyphs instead of one glyph per pixel
+    #[arg(long)]
+    braille: bool,
+    /// Terminal columns each pixel is drawn across, 1 for terminals with square cells
+    /// [default: --cell-aspect rounded]
+    #[arg(long, value_name = "COLUMNS", conflicts_with_all = ["braille", "half_block"])]
+    pixel_width: Option<u32>,
+    /// Height of the terminal's cells over their width, for fonts that aren't twice
+    /// as tall as wide. Frames are stretched vertically to keep their shape on them
+    #[arg(long, value_name = "RATIO", default_value_t = 2.0)]
+    cell_aspect: f32,
+    /// Draw two pixels per cell as a colored half block, doubling the vertical
+    /// resolution
+    #[arg(long, conflicts_with_all = ["braille", "dither"])]
+    half_block: bool,
+    /// Only redraw the cells that changed since the previous frame
+    #[arg(long)]
+    diff: bool,
+    /// Pick glyphs by the luminance of the linear light rather than of the raw sRGB
+    /// values, which renders the midtones of photos more faithfully
+    #[arg(long)]
+    gamma: bool,
+    /// Weights of red, green and blue in the luminance glyphs are picked by
+    /// [default: rec601, rec709 with --gamma]
+    #[arg(long, value_enum)]
+    luma: Option<Luma>,
+    /// Pick the 256-color palette entries by how different colors look, in CIELAB,
+    /// rather than by sRGB distance, which keeps the hues of photos truer
+    #[arg(long)]
+    lab: bool,
+    /// Round the colors to at most N of them, fewer distinct colors need fewer escapes
+    /// between cells. Taken as the cube of the levels of each channel below it, so 64
+    /// keeps 4 levels of red, green and blue [default: all of them]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(8..))]
+    colors: Option<u32>,
+    /// Keep only the top BITS bits of each color channel, from 1 to 8, for a flat
+    /// poster look that also leaves fewer colors to write [default: 8]
+    #[arg(long, value_name = "BITS")]
+    posterize: Option<u8>,
+    /// Stretch colors away from mid-gray by this factor, below 1 flattens them
+    #[arg(long, default_value_t = 1.0)]
+    contrast: f32,
+    /// Brighten colors by this fraction of full brightness, from -1 to 1, after the
+    /// contrast
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    brightness: f32,
+    /// Color to composite transparent pixels over, as `#rrggbb` or `r,g,b`
+    /// [default: the terminal's background]
+    #[arg(long, value_name = "COLOR", value_parser = parse_color)]
+    bg: Option<[u8; 3]>,
+    /// Map dark pixels to dense glyphs, for light terminal themes
+    #[arg(long)]
+    invert: bool,
+    /// Draw outlines with `|`, `-`, `/` and `\` along the edges found in the image,
+    /// instead of shading it
+    #[arg(long, conflicts_with_all = ["braille", "half_block", "dither"])]
+    edges: bool,
+    /// Shade with the ramp, but draw `|`, `-`, `/` and `\` along edges at least this
+    /// strong, from 0 for the faintest to 1 for a step from black to white
+    #[arg(long, value_name = "STRENGTH", conflicts_with_all = ["edges", "braille", "half_block", "dither"])]
+    edge_threshold: Option<f32>,
+    /// Dither the glyphs so gradients don't band on a short ramp
+    #[arg(long, conflicts_with = "braille")]
+    dither: bool,
+    /// Show every frame even when falling behind, instead of skipping ahead
+    #[arg(long)]
+    no_skip: bool,
+    /// Seconds to wait for a frame that's late, such as from a pipe nothing is written
+    /// to, before saying so below the frame
+    #[arg(long, value_name = "SECONDS", default_value_t = 2, value_parser = clap::value_parser!(u64).range(1..))]
+    idle_timeout: u64,
+    /// Give up once --idle-timeout passes without a frame, instead of waiting on
+    #[arg(long)]
+    exit_on_idle: bool,
+    /// Stop at the first frame that fails to decode, instead of warning and going on
+    /// without it
+    #[arg(long)]
+    strict: bool,
+    /// Only play these image formats from a directory, by extension [default: any
+    /// format that can be decoded]
+    #[arg(long, value_name = "EXTENSIONS", value_delimiter = ',', value_parser = parse_format)]
+    formats: Vec<ImageFormat>,
+    /// Show photos as they're stored rather than turned upright by the orientation
+    /// their EXIF data gives
+    #[arg(long)]
+    no_auto_rotate: bool,
+    /// Read raw frames of WIDTHxHEIGHT pixels written back to back, such as the output
+    /// of `ffmpeg -f rawvideo`, and play them as they arrive until the stream ends
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_size, conflicts_with_all = ["formats", "cache"])]
+    raw: Option<(u32, u32)>,
+    /// Pixel layout of the raw frames
+    #[arg(long, value_enum, default_value_t = RawFormat::Rgb24, requires = "raw")]
+    raw_format: RawFormat,
+    /// Read raw frames like --raw, of the size and pixel layout given in the JSON
+    /// file at FILE, such as `{"width": 384, "height": 216, "format": "rgb24"}`. A
+    /// `frame_bytes` it declares has to agree with them
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["raw", "raw_format", "formats", "cache"])]
+    raw_info: Option<PathBuf>,
+    /// Show frames as fast as they can be converted and report the rate reached at
+    /// the end, for benchmarking
+    #[arg(long)]
+    uncapped: bool,
+    /// Time the decoding, conversion and writing of each frame, and print the
+    /// shortest, mean, 95th percentile and longest times of each to stderr at the end
+    #[arg(long)]
+    stats: bool,
+    /// Show the file and number of the frame on screen in the terminal's window
+    /// title, which is set back once playback ends on terminals that keep it
+    #[arg(long)]
+    title: bool,
+    /// Leave the last frame on screen on exit instead of clearing it, by drawing on
+    /// the terminal's own screen rather than a separate one
+    #[arg(long)]
+    keep_last: bool,
+    /// Leave the frames undrawn, to measure decoding and conversion alone
+    #[arg(long, requires = "uncapped")]
+    no_draw: bool,
+    /// Play the sound track in FILE along with the frames, which keep to its position
+    /// rather than the clock
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["uncapped", "output", "export_image", "dump_dir"])]
+    audio: Option<PathBuf>,
+    /// Decode up to N frames ahead on a background thread, everything up front if N
+    /// covers the whole sequence
+    #[arg(long, value_name = "FRAMES")]
+    cache: Option<usize>,
+    /// Convert up to N frames ahead of the one on screen, which evens out frames that
+    /// take longer to convert at the cost of reacting later to skips and keys
+    #[arg(long, value_name = "N", default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..=64))]
+    lookahead: u16,
+    /// Resampling filter used to scale frames down to the terminal
+    #[arg(long, value_enum, default_value_t = Filter::Nearest)]
+    filter: Filter,
+    /// How each pixel of the grid is read from the source pixels it covers when
+    /// they're scaled down by picking: `average` takes their mean, which keeps fine
+    /// detail from shimmering
+    #[arg(long, value_enum, default_value_t = Sample::Point)]
+    sample: Sample,
+    /// Threads converting the frames [default: one per core]. The thread drawing
+    /// them, and the one --cache decodes on, come on top
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: Option<u16>,
+    /// Rows each thread converts at a time [default: the frame split evenly over the
+    /// threads]
+    #[arg(long, value_name = "ROWS")]
+    block_rows: Option<u32>,
+    /// Seed for anything the conversion picks at random, the same seed and input
+    /// always give the same output
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Convert only the WIDTHxHEIGHT pixels at X,Y of each frame, to zoom in on a
+    /// detail or cut off black bars. It has to lie within the frames
+    #[arg(long, value_name = "X,Y,WIDTH,HEIGHT", value_parser = parse_crop)]
+    crop: Option<(u32, u32, u32, u32)>,
+    /// Glyph ramp used to map luminance to characters: `short`, `long`, or glyphs of
+    /// your own from the darkest to the lightest
+    #[arg(long, default_value = "short", value_parser = parse_ramp)]
+    ramp: Ramp,
+    /// Read the glyphs of the ramp from a file instead, darkest first
+    #[arg(long, value_name = "PATH", conflicts_with = "ramp")]
+    ramp_file: Option<PathBuf>,
+    /// How pixel colors are written to the terminal, detected from the environment
+    /// when omitted
+    #[arg(long, value_enum)]
+    color: Option<ColorMode>,
+    /// Write 24-bit colors even though the terminal doesn't advertise them, without
+    /// warning
+    #[arg(long, conflicts_with = "color")]
+    force_truecolor: bool,
+    /// Where the frame sits on a terminal larger than it, the rest is left blank
+    #[arg(long, value_enum, default_value_t = Align::TopLeft)]
+    align: Align,
+    /// Send the frames as pictures through a terminal graphics protocol instead of
+    /// drawing them with glyphs; `auto` picks the one the terminal is known to show,
+    /// and keeps to glyphs on a terminal that shows none
+    #[arg(long, value_enum, conflicts_with_all = ["output", "export_image", "dump_dir"])]
+    protocol: Option<ProtocolChoice>,
+    /// Show the frame number and playback rate below the frame, `s` toggles it
+    #[arg(long)]
+    status: bool,
+    /// Write the frames as text to FILE instead of playing them, `-` for stdout. Also
+    /// the default when stdout is not a terminal
+    #[arg(long, short, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Draw the first frame, glyphs and colors, into a PNG or any other image format
+    /// at FILE instead of playing
+    #[arg(long, value_name = "FILE", conflicts_with = "output")]
+    export_image: Option<PathBuf>,
+    /// Write each frame as text to a file of its own in DIR instead of playing,
+    /// numbered from 1 with enough zeros in front to sort in order
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["output", "export_image"])]
+    dump_dir: Option<PathBuf>,
+    /// TrueType or OpenType font to draw the exported image with [default: the bundled
+    /// DejaVu Sans Mono]
+    #[arg(long, value_name = "PATH", requires = "export_image")]
+    font: Option<PathBuf>,
+    /// Size of the exported font in pixels, which sets the size of its cells
+    #[arg(long, value_name = "PIXELS", default_value_t = 16.0, value_parser = parse_font_size, requires = "export_image")]
+    font_size: f32,
+    /// Write the number of the exported frame and the time it's shown at into the top
+    /// left corner of the image
+    #[arg(long, requires = "export_image")]
+    burn_index: bool,
+    /// Write a contact sheet of COLUMNSxROWS frames spread evenly over the sequence,
+    /// from the first to the last, as text or with --export-image as an image, and
+    /// exit
+    #[arg(long, value_name = "COLUMNSxROWS", value_parser = parse_grid, conflicts_with_all = ["raw", "raw_info", "once", "dump_dir", "burn_index", "cache", "transition", "audio", "protocol"])]
+    montage: Option<(u32, u32)>,
+    /// Read defaults for the options from this TOML file [default: ascii_art.toml in
+    /// the working directory, if there is one]
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
 }
 
-impl DoubleBuffer {
-    fn new(width: u32, height: u32) -> Self {
-        let front_box = Box::new(FrameData {
-            width,
-            height,
-            data: Vec::with_capacity((width * height * 20) as usize),
+/// Parses the command line over the defaults of the config file. Options given on the
+/// command line win over the file, and so do the options they conflict with, the
+/// file in turn over the built-in defaults.
+fn parse_args() -> anyhow::Result<Args> {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let path = match args.config {
+        Some(path) => path,
+        None if Path::new(CONFIG_FILE).is_file() => PathBuf::from(CONFIG_FILE),
+        None => return Ok(args),
+    };
+    let config = Config::load(&path)?;
+    let command = Args::command();
+    let given = command
+        .get_arguments()
+        .filter(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .collect::<Vec<_>>();
+    let mut argv = std::env::args_os().take(1).collect::<Vec<OsString>>();
+    for (id, value) in config.args() {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id)
+            .expect("config options are arguments");
+        let overridden = given.iter().any(|other| {
+            other.get_id() == arg.get_id()
+                || command
+                    .get_arg_conflicts_with(other)
+                    .iter()
+                    .chain(&command.get_arg_conflicts_with(arg))
+                    .any(|conflict| [arg.get_id(), other.get_id()].contains(&conflict.get_id()))
         });
-        let back_box = Box::new(FrameData {
-            width,
-            height,
-            data: Vec::with_capacity((width * height * 20) as usize),
-        });
-        let temp_box = Box::new(FrameData {
-            width,
-            height,
-            data: Vec::with_capacity((width * height * 20) as usize),
+        if overridden {
+            continue;
+        }
+        let long = arg.get_long().expect("config options have long flags");
+        argv.push(match value {
+            Some(value) => format!("--{long}={value}").into(),
+            None => format!("--{long}").into(),
         });
-        DoubleBuffer {
-            front: AtomicPtr::new(Box::into_raw(front_box)),
-            back: AtomicPtr::new(Box::into_raw(back_box)),
-            temp: AtomicPtr::new(Box::into_raw(temp_box)),
+    }
+    argv.extend(std::env::args_os().skip(1));
+    // The command line alone parsed, so whatever fails now comes from the file. Only
+    // the line naming the problem is kept, the usage after it is about the flags
+    let matches = Args::command().try_get_matches_from(argv).map_err(|err| {
+        let message = err.render().to_string();
+        let problem = message.lines().next().unwrap_or_default();
+        anyhow!(
+            "{}: {}",
+            path.display(),
+            problem.trim_start_matches("error: ")
+        )
+    })?;
+    Ok(Args::from_arg_matches(&matches)?)
+}
+
+/// Values of `--transition`.
+#[derive(Clone, Copy, ValueEnum)]
+enum Transition {
+    /// Fade the colors of one frame into those of the next, in CIELAB with `--lab`
+    Crossfade,
+}
+
+/// Values of `--protocol`.
+#[derive(Clone, Copy, ValueEnum)]
+enum ProtocolChoice {
+    Auto,
+    Kitty,
+    Iterm,
+    Sixel,
+}
+
+impl ProtocolChoice {
+    fn protocol(self) -> Option<Protocol> {
+        match self {
+            ProtocolChoice::Auto => Protocol::detect(),
+            ProtocolChoice::Kitty => Some(Protocol::Kitty),
+            ProtocolChoice::Iterm => Some(Protocol::Iterm),
+            ProtocolChoice::Sixel => Some(Protocol::Sixel),
         }
     }
+}
 
-    fn swap(&self) {
-        let back_ptr = self.back.load(std::sync::atomic::Ordering::SeqCst);
-        let front_ptr = self
-            .front
-            .swap(back_ptr, std::sync::atomic::Ordering::SeqCst);
-        self.back
-            .store(front_ptr, std::sync::atomic::Ordering::SeqCst);
+fn parse_font_size(value: &str) -> Result<f32, String> {
+    let size: f32 = value.parse().map_err(|err| format!("{err}"))?;
+    if !(1.0..=512.0).contains(&size) {
+        return Err("must be a number from 1 to 512".into());
     }
+    Ok(size)
+}
 
-    fn front(&self) -> &FrameData {
-        unsafe { &*self.front.load(std::sync::atomic::Ordering::SeqCst) }
+fn parse_ramp(value: &str) -> Result<Ramp, String> {
+    match value {
+        "short" => Ok(Ramp::Short),
+        "long" => Ok(Ramp::Long),
+        // Options live for the whole run
+        glyphs => Ok(Ramp::Custom(glyphs.chars().collect::<Vec<_>>().leak())),
     }
-    fn temp_mut(&self) -> &mut FrameData {
-        unsafe { &mut *self.temp.load(std::sync::atomic::Ordering::SeqCst) }
+}
+
+fn parse_format(value: &str) -> Result<ImageFormat, String> {
+    ImageFormat::from_extension(value.trim_start_matches('.'))
+        .ok_or_else(|| format!("{value:?} is not an image format"))
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("expected WIDTHxHEIGHT, found {value:?}");
+    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+    match (width.parse::<u32>(), height.parse::<u32>()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(invalid()),
     }
-    fn temp_to_back(&self) {
-        let back_ptr = self.back.load(std::sync::atomic::Ordering::SeqCst);
-        let temp_ptr = self
-            .temp
-            .swap(back_ptr, std::sync::atomic::Ordering::SeqCst);
-        self.back
-            .store(temp_ptr, std::sync::atomic::Ordering::SeqCst);
+}
+
+fn parse_grid(value: &str) -> Result<(u32, u32), String> {
+    let (columns, rows) =
+        parse_size(value).map_err(|_| format!("expected COLUMNSxROWS, found {value:?}"))?;
+    // A cell to each frame and one between them, the least a sheet takes
+    let side = |count: u32| count.checked_mul(2).map(|cells| cells - 1);
+    match side(columns).zip(side(rows)) {
+        Some((width, height)) if width.checked_mul(height).is_some() => Ok((columns, rows)),
+        _ => Err(format!("a grid of {columns}x{rows} frames is too large")),
+    }
+}
+
+fn parse_crop(value: &str) -> Result<(u32, u32, u32, u32), String> {
+    let invalid = || format!("expected X,Y,WIDTH,HEIGHT, found {value:?}");
+    let numbers = value
+        .split(',')
+        .map(|number| number.trim().parse::<u32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match numbers[..] {
+        [x, y, width, height] if width > 0 && height > 0 => Ok((x, y, width, height)),
+        _ => Err(invalid()),
     }
 }
 
-impl Drop for DoubleBuffer {
+fn parse_color(value: &str) -> Result<[u8; 3], String> {
+    let invalid = || format!("expected #rrggbb or r,g,b, found {value:?}");
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+        return Ok([channel(0)?, channel(2)?, channel(4)?]);
+    }
+    let channels = value
+        .split(',')
+        .map(|channel| channel.trim().parse::<u8>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    channels.try_into().map_err(|_| invalid())
+}
+
+/// Playback state shared by the input loop, the CPU thread and the render thread.
+struct Playback {
+    running: AtomicBool,
+    paused: AtomicBool,
+    /// Frames the render thread may still show while paused
+    steps: AtomicUsize,
+    /// Index of the frame currently on screen
+    shown: AtomicUsize,
+    seek: Mutex<Seek>,
+    /// Frame size and position the CPU thread should switch to
+    resize: Mutex<Option<Placement>>,
+    /// Region of the frames the CPU thread should convert from now on, `Some(None)`
+    /// for the whole of them
+    view: Mutex<Option<Option<Region>>>,
+    /// Frames the CPU thread should skip to catch up with the clock
+    skip: AtomicUsize,
+    /// Frames skipped so far
+    dropped: AtomicUsize,
+    /// Frames converted and handed over that the render thread hasn't taken yet
+    ready: AtomicUsize,
+    /// Show the status line below the frame
+    status: AtomicBool,
+    /// Frame number typed in so far, for `g` to jump to
+    typed: Mutex<Option<usize>>,
+    /// Stop at a frame that fails to decode, rather than holding the one before it
+    strict: bool,
+    /// Frames that failed to decode, each once, to report after playback
+    failed: Mutex<Vec<(usize, anyhow::Error)>>,
+    stats: Stats,
+}
+
+struct Seek {
+    epoch: u64,
+    target: Option<usize>,
+}
+
+impl Playback {
+    fn new(status: bool, strict: bool, stats: bool) -> Self {
+        Playback {
+            running: AtomicBool::new(true),
+            paused: AtomicBool::new(false),
+            steps: AtomicUsize::new(0),
+            shown: AtomicUsize::new(0),
+            seek: Mutex::new(Seek {
+                epoch: 0,
+                target: None,
+            }),
+            resize: Mutex::new(None),
+            view: Mutex::new(None),
+            skip: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+            ready: AtomicUsize::new(0),
+            status: AtomicBool::new(status),
+            typed: Mutex::new(None),
+            strict,
+            failed: Mutex::new(Vec::new()),
+            stats: Stats::new(stats),
+        }
+    }
+
+    /// Sends a converted frame to the render thread, counting it as ready. `false`
+    /// once the render thread has hung up.
+    fn hand_over(&self, producer: &Producer, frame: FrameData) -> bool {
+        self.ready.fetch_add(1, Ordering::Relaxed);
+        producer.hand_over(frame)
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    fn toggle_pause(&self) {
+        self.paused.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    /// Lets the render thread show one more frame while paused.
+    fn step(&self) {
+        self.steps.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Takes one pending step, returns false if there was none.
+    fn take_step(&self) -> bool {
+        self.steps
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |steps| {
+                steps.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// Restarts the CPU thread at `index`. Frames converted before the seek carry an
+    /// older epoch, so the render thread can tell them apart and drop them. A paused
+    /// player shows the frame at `index` and stays on it, however many seeks came
+    /// before it got there.
+    fn seek(&self, index: usize) {
+        let mut seek = self.seek.lock().unwrap();
+        seek.epoch += 1;
+        seek.target = Some(index);
+        if self.is_paused() {
+            self.steps.store(1, Ordering::Relaxed);
+        }
+    }
+
+    fn take_seek(&self) -> Option<(usize, u64)> {
+        let mut seek = self.seek.lock().unwrap();
+        seek.target.take().map(|target| (target, seek.epoch))
+    }
+
+    fn epoch(&self) -> u64 {
+        self.seek.lock().unwrap().epoch
+    }
+
+    /// Switches the frames converted from now on to `placement`.
+    fn resize(&self, placement: Placement) {
+        *self.resize.lock().unwrap() = Some(placement);
+    }
+
+    fn take_resize(&self) -> Option<Placement> {
+        self.resize.lock().unwrap().take()
+    }
+
+    /// Switches the frames converted from now on to the region `crop`, as `--crop`
+    /// takes it.
+    fn view(&self, crop: Option<Region>) {
+        *self.view.lock().unwrap() = Some(crop);
+    }
+
+    fn take_view(&self) -> Option<Option<Region>> {
+        self.view.lock().unwrap().take()
+    }
+
+    /// Makes the CPU thread jump `frames` ahead without decoding them.
+    fn skip(&self, frames: usize) {
+        self.skip.fetch_add(frames, Ordering::Relaxed);
+        self.dropped.fetch_add(frames, Ordering::Relaxed);
+    }
+
+    fn take_skip(&self) -> usize {
+        self.skip.swap(0, Ordering::Relaxed)
+    }
+
+    fn shows_status(&self) -> bool {
+        self.status.load(Ordering::Relaxed)
+    }
+
+    fn toggle_status(&self) {
+        self.status.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    /// Appends `digit` to the frame number being typed in.
+    fn type_digit(&self, digit: u32) {
+        let mut typed = self.typed.lock().unwrap();
+        *typed = Some(
+            typed
+                .unwrap_or(0)
+                .saturating_mul(10)
+                .saturating_add(digit as usize),
+        );
+    }
+
+    fn typed(&self) -> Option<usize> {
+        *self.typed.lock().unwrap()
+    }
+
+    fn take_typed(&self) -> Option<usize> {
+        self.typed.lock().unwrap().take()
+    }
+
+    /// Notes that frame `index` failed to decode, unless it did before on an earlier
+    /// pass.
+    fn fail(&self, index: usize, error: anyhow::Error) {
+        let mut failed = self.failed.lock().unwrap();
+        if failed.iter().all(|(failed, _)| *failed != index) {
+            failed.push((index, error));
+        }
+    }
+}
+
+/// Pushes the window title onto the terminal's stack of them, as xterm and most
+/// terminals after it keep one. The rest ignore it, and keep the last title set.
+const SAVE_TITLE: &str = "\x1b[22;0t";
+/// Pops the title pushed by `SAVE_TITLE` back into place.
+const RESTORE_TITLE: &str = "\x1b[23;0t";
+
+fn restore_terminal(alternate_screen: bool, title: bool) {
+    let mut stdout = std::io::stdout();
+    let _ = disable_raw_mode();
+    let _ = execute!(
+        stdout,
+        crossterm::style::ResetColor,
+        crossterm::cursor::Show
+    );
+    if alternate_screen {
+        let _ = execute!(stdout, LeaveAlternateScreen);
+    }
+    if title {
+        let _ = write!(stdout, "{}", RESTORE_TITLE);
+        let _ = stdout.flush();
+    }
+}
+
+/// Switches the terminal into playback mode and undoes it when dropped, so early
+/// returns through `?` don't leave the shell in raw mode. Only the steps that
+/// succeeded are undone. It's only made for a terminal on stdout; redirected
+/// output never touches the terminal modes or the cursor. Without the alternate
+/// screen, what was drawn stays on the terminal's own screen once it's dropped.
+struct TerminalGuard {
+    raw_mode: bool,
+    alternate_screen: bool,
+    cursor_hidden: bool,
+    title_saved: bool,
+}
+
+impl TerminalGuard {
+    fn new(alternate_screen: bool, title: bool) -> std::io::Result<Self> {
+        let mut stdout = std::io::stdout();
+        let mut guard = TerminalGuard {
+            raw_mode: false,
+            alternate_screen: false,
+            cursor_hidden: false,
+            title_saved: false,
+        };
+        enable_raw_mode()?;
+        guard.raw_mode = true;
+        // Draw on a separate screen so the user's scrollback survives playback
+        if alternate_screen {
+            execute!(stdout, EnterAlternateScreen)?;
+            guard.alternate_screen = true;
+        }
+        // A terminal that can't hide the cursor plays with it showing
+        guard.cursor_hidden = execute!(stdout, crossterm::cursor::Hide).is_ok();
+        if title {
+            write!(stdout, "{}", SAVE_TITLE)?;
+            stdout.flush()?;
+            guard.title_saved = true;
+        }
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
     fn drop(&mut self) {
-        let front_ptr = self.front.load(std::sync::atomic::Ordering::SeqCst);
-        let back_ptr = self.back.load(std::sync::atomic::Ordering::SeqCst);
-        let temp_ptr = self.temp.load(std::sync::atomic::Ordering::SeqCst);
-        unsafe {
-            drop(Box::from_raw(front_ptr));
-            drop(Box::from_raw(back_ptr));
-            drop(Box::from_raw(temp_ptr));
+        let mut stdout = std::io::stdout();
+        if self.title_saved {
+            let _ = write!(stdout, "{}", RESTORE_TITLE);
+        }
+        // The shell's prompt comes in its own colors, whatever a frame left set
+        let _ = execute!(stdout, crossterm::style::ResetColor);
+        if self.cursor_hidden {
+            let _ = execute!(stdout, crossterm::cursor::Show);
+        }
+        if self.alternate_screen {
+            let _ = execute!(stdout, LeaveAlternateScreen);
+        }
+        if self.raw_mode {
+            let _ = disable_raw_mode();
         }
     }
 }
 
-fn get_path() -> Vec<PathBuf> {
-    let mut paths = Vec::with_capacity(4989);
-    for i in 1..=4989 {
-        let path = format!("target/images/{}.jpeg", i);
-        paths.push(PathBuf::from(path));
+fn is_quit(key: KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => true,
+        // Raw mode swallows SIGINT, so Ctrl-C arrives as a key press
+        KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+        _ => false,
+    }
+}
+
+/// Pixels of the frames at X,Y, WIDTH across and HEIGHT down, like `--crop` takes
+/// them.
+type Region = (u32, u32, u32, u32);
+
+/// How much closer each zoom step gets, two of them double the size.
+const ZOOM_STEP: f64 = std::f64::consts::SQRT_2;
+/// Zoom steps in at most, 64 times the size.
+const MAX_ZOOM: i32 = 12;
+
+/// Part of the frames the view is zoomed in on with the keys. It keeps the shape of
+/// the region zoomed out all the way, so the frame fitted to it stays the same size.
+struct Zoom {
+    /// The region zoomed out all the way, `--crop`'s if it's given
+    crop: Option<Region>,
+    /// Size of the frames, in pixels
+    size: (u32, u32),
+    /// Steps zoomed in
+    level: i32,
+    /// Center of the view, in pixels of the frames
+    center: (f64, f64),
+}
+
+impl Zoom {
+    fn new(crop: Option<Region>, size: (u32, u32)) -> Self {
+        let (x, y, width, height) = crop.unwrap_or((0, 0, size.0, size.1));
+        Zoom {
+            crop,
+            size,
+            level: 0,
+            center: (
+                x as f64 + width as f64 / 2.0,
+                y as f64 + height as f64 / 2.0,
+            ),
+        }
+    }
+
+    fn is_zoomed(&self) -> bool {
+        self.level > 0
+    }
+
+    /// Region in view, as `--crop` takes it, `None` for the whole of the frames.
+    fn region(&self) -> Option<Region> {
+        if !self.is_zoomed() {
+            return self.crop;
+        }
+        let (x, y, width, height) = self.crop.unwrap_or((0, 0, self.size.0, self.size.1));
+        let factor = ZOOM_STEP.powi(self.level);
+        let view_width = ((width as f64 / factor).round() as u32).clamp(1, width);
+        let view_height = ((height as f64 / factor).round() as u32).clamp(1, height);
+        // Kept within the region zoomed out all the way
+        let left = (self.center.0 - view_width as f64 / 2.0)
+            .round()
+            .clamp(x as f64, (x + width - view_width) as f64);
+        let top = (self.center.1 - view_height as f64 / 2.0)
+            .round()
+            .clamp(y as f64, (y + height - view_height) as f64);
+        Some((left as u32, top as u32, view_width, view_height))
+    }
+
+    /// Zooms `steps` in, or out if negative. Returns whether the view changed.
+    fn zoom(&mut self, steps: i32) -> bool {
+        let level = (self.level + steps).clamp(0, MAX_ZOOM);
+        let changed = level != self.level;
+        self.level = level;
+        self.settle();
+        changed
+    }
+
+    /// Moves the view by a tenth of its size per step across and down. Returns
+    /// whether it moved, which it doesn't past the edges.
+    fn pan(&mut self, (across, down): (i32, i32)) -> bool {
+        let before = self.region();
+        if let Some((_, _, width, height)) = before {
+            self.center.0 += across as f64 * (width as f64 / 10.0).max(1.0);
+            self.center.1 += down as f64 * (height as f64 / 10.0).max(1.0);
+        }
+        self.settle();
+        self.region() != before
+    }
+
+    /// Moves the center to that of the region in view, so panning past an edge
+    /// doesn't have to be undone before the view moves back.
+    fn settle(&mut self) {
+        if let Some((x, y, width, height)) = self.region() {
+            self.center = (
+                x as f64 + width as f64 / 2.0,
+                y as f64 + height as f64 / 2.0,
+            );
+        }
     }
-    paths
 }
 
-#[allow(dead_code)]
-fn preload_images(paths: &[PathBuf]) -> Vec<image::DynamicImage> {
-    let mut images = Vec::with_capacity(paths.len());
-    for path in paths.iter() {
-        let img = image::open(path).unwrap();
-        images.push(img);
+/// Size of the frames in cells and where on the terminal they're drawn.
+#[derive(Clone, Copy)]
+struct Placement {
+    size: (u32, u32),
+    origin: (u32, u32),
+}
+
+/// Blends shown per second of a transition, however fast the frames themselves go.
+const TRANSITION_FPS: u64 = 30;
+
+/// A crossfade under way from the last frame converted into the next one.
+struct Fade {
+    from: DynamicImage,
+    to: DynamicImage,
+    /// Blends converted so far, out of `steps`
+    step: u32,
+    steps: u32,
+}
+
+/// How the CPU thread goes through the frames.
+struct Sequencing {
+    /// Passes over the frames, `None` goes on forever
+    loops: Option<u32>,
+    /// How long a frame takes to fade into the next one, which it cuts to without
+    transition: Option<Duration>,
+    /// Hand over a frame identical to the one before as it was converted then,
+    /// rather than converting it again
+    dedupe: bool,
+}
+
+/// Body of the CPU thread: converts frames into the buffers the render thread
+/// hands back, until the loop count runs out or either side stops. With a
+/// transition, a frame doesn't cut to the next one but fades into it, through
+/// blends of the two converted in between.
+fn convert_frames(
+    mut frames: Source,
+    placement: Placement,
+    Sequencing {
+        loops,
+        transition,
+        dedupe,
+    }: Sequencing,
+    mut options: RenderOptions,
+    playback: &Playback,
+    producer: Producer,
+) -> anyhow::Result<()> {
+    let frame_count = frames.len();
+    // Frames of other sizes than the first are fitted into its shape
+    let canvas = frames.dimensions()?;
+    let mut index = 0;
+    let mut epoch = 0;
+    let mut passes = 0;
+    // Cells of the last frame handed over, in diff mode
+    let mut previous = Vec::new();
+    // The last frame converted and its index, kept to fade from with a transition
+    let mut last: Option<(usize, DynamicImage)> = None;
+    let mut fade: Option<Fade> = None;
+    // Hash of the last frame converted and the escapes it came out as, with
+    // --dedupe. In diff mode a frame the same as it draws nothing, so they're left
+    // out; gone once anything else changes the cells on screen
+    let mut converted: Option<(u64, Vec<u8>)> = None;
+    let Placement {
+        size: (mut width, mut height),
+        mut origin,
+    } = placement;
+    let mut clear = false;
+    while playback.is_running() {
+        // The render thread hangs up once it stops drawing
+        let Some(mut back) = producer.next_buffer() else {
+            break;
+        };
+        if let Some(placement) = playback.take_resize() {
+            ((width, height), origin) = (placement.size, placement.origin);
+            clear = true;
+            previous.clear();
+            converted = None;
+        }
+        // Zooming changes every cell
+        if let Some(crop) = playback.take_view() {
+            options.crop = crop;
+            previous.clear();
+            converted = None;
+        }
+        if (back.width, back.height) != (width, height) {
+            back = FrameData::new(width, height);
+        }
+        if let Some((target, seek_epoch)) = playback.take_seek() {
+            index = target.min(frame_count - 1);
+            epoch = seek_epoch;
+            // Frames before the seek never reach the screen, redraw everything
+            previous.clear();
+            converted = None;
+            // A seek cuts straight to its frame
+            (fade, last) = (None, None);
+        }
+        // Blends that fall behind only make the fade take longer, the frames on
+        // either side of it are never skipped
+        let skip = playback.take_skip();
+        if fade.is_none() {
+            index += skip;
+        }
+        if index >= frame_count {
+            passes += (index / frame_count) as u32;
+            if loops.is_some_and(|loops| passes >= loops) {
+                break;
+            }
+            index %= frame_count;
+        }
+        back.delay = frames.delay(index);
+        back.index = index;
+        back.epoch = epoch;
+        back.origin = origin;
+        // A frame being faded into is decoded once, when the fade starts
+        let mut decoded = None;
+        if fade.is_none() {
+            let img = match playback.stats.time(Stage::Decode, || frames.frame(index)) {
+                Ok(Some(img)) => img,
+                // A stream has run dry
+                Ok(None) => break,
+                Err(err) if playback.strict => return Err(err),
+                // Nothing gets drawn, so the previous frame stays on screen in its place.
+                // A pending clear waits for a frame that draws
+                Err(err) => {
+                    playback.fail(index, err);
+                    back.data.clear();
+                    back.clear = false;
+                    if !playback.hand_over(&producer, back) {
+                        break;
+                    }
+                    index += 1;
+                    continue;
+                }
+            };
+            match (transition, last.take()) {
+                // The frame before fades into this one first, unless it's the same
+                (Some(transition), Some((from_index, from))) if from_index != index => {
+                    let steps = (transition.as_millis() as u64 * TRANSITION_FPS / 1000).max(1);
+                    fade = Some(Fade {
+                        from,
+                        to: letterbox(&img, canvas, options.filter).into_owned(),
+                        step: 0,
+                        steps: steps as u32,
+                    });
+                }
+                _ => decoded = Some(img),
+            }
+        }
+        // Blends are shown for an equal share of the transition each, under the index
+        // of the frame they lead to
+        if let (Some(transition), Some(fading)) = (transition, &mut fade) {
+            if fading.step < fading.steps {
+                fading.step += 1;
+                let t = fading.step as f32 / (fading.steps + 1) as f32;
+                playback.stats.time(Stage::Convert, || {
+                    let img = crossfade(&fading.from, &fading.to, t, options.lab);
+                    render_frame(&img, &mut back, None, &options)
+                });
+                back.delay = Some(transition / fading.steps);
+                back.clear = std::mem::take(&mut clear);
+                converted = None;
+                if !playback.hand_over(&producer, back) {
+                    break;
+                }
+                continue;
+            }
+        }
+        // Once they're done, the frame faded into comes as itself
+        let img = match fade.take() {
+            Some(fading) => Cow::Owned(fading.to),
+            None => decoded.expect("a frame is decoded unless it's faded into"),
+        };
+        if transition.is_some() {
+            last = Some((index, letterbox(&img, canvas, options.filter).into_owned()));
+        }
+        let hash = dedupe.then(|| frame_hash(&img));
+        match &converted {
+            Some((last, data)) if hash == Some(*last) => {
+                back.data.clone_from(data);
+                if options.diff {
+                    back.cells.clone_from(&previous);
+                }
+            }
+            _ => {
+                let diff_against = (!previous.is_empty()).then_some(previous.as_slice());
+                playback.stats.time(Stage::Convert, || {
+                    let img = letterbox(&img, canvas, options.filter);
+                    render_frame(&img, &mut back, diff_against, &options)
+                });
+                if options.diff {
+                    previous.clone_from(&back.cells);
+                }
+                converted = hash.map(|hash| match options.diff {
+                    true => (hash, Vec::new()),
+                    false => (hash, back.data.clone()),
+                });
+            }
+        }
+        back.clear = std::mem::take(&mut clear);
+        if !playback.hand_over(&producer, back) {
+            break;
+        }
+        index += 1;
     }
-    images
+    Ok(())
 }
 
-fn main() {
-    let size = 5;
+/// Hash of the size and pixels of `img`, which tells identical frames apart from
+/// the rest short of a collision.
+fn frame_hash(img: &DynamicImage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (img.dimensions(), img.color()).hash(&mut hasher);
+    img.as_bytes().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// How the render thread schedules and draws frames.
+struct Pacing {
+    /// How long a frame stays on screen when the source doesn't say
+    frame_time: Duration,
+    /// Skip frames in the source while behind schedule
+    skip: bool,
+    /// Show frames as fast as they come, ignoring the schedule
+    uncapped: bool,
+    /// Sound track whose position decides when frames are shown, in place of the
+    /// clock, until it ends
+    audio: Option<Audio>,
+    /// Leave the cursor below the last frame once done, for it to stay on screen
+    keep_last: bool,
+    /// Names of the frames for the window title, which is left alone without. A
+    /// single name goes for every frame
+    titles: Option<Vec<String>>,
+    /// How long to wait for a frame before showing that it's late
+    idle: Duration,
+    /// Stop playback once a frame is that late, rather than waiting on
+    exit_on_idle: bool,
+}
+
+/// Body of the render thread: writes each converted frame to `sink` on schedule
+/// and returns the previous buffer to the CPU thread.
+fn render_frames(
+    front: FrameData,
+    frame_count: usize,
+    pacing: Pacing,
+    playback: &Playback,
+    sink: &mut dyn OutputSink,
+    consumer: Consumer,
+) -> anyhow::Result<usize> {
+    let mut front = front;
+    let mut shown = 0;
+    // Everything that goes on screen for a frame is gathered here and written in one
+    // go, unlike a `BufWriter` it grows to fit the largest frame rather than writing
+    // through once full
+    let mut out = Vec::with_capacity(front.data.capacity());
+    // When the frame on screen is due to make way for the next one; frames are
+    // scheduled off the previous deadline rather than the clock, so time spent
+    // drawing or oversleeping never adds up into drift
+    let mut deadline: Option<Instant> = None;
+    // Epoch and index of the last frame shown against the audio track, a frame that
+    // doesn't follow on from it moves the track to its own place
+    let mut synced: Option<(u64, usize)> = None;
+    // A clear requested by a dropped frame still has to happen before the next one
+    let mut clear = false;
+    // Frames shown since `window_start`, counting the one that started it, for the
+    // rate on the status line
+    let mut window_start = Instant::now();
+    let mut window_frames = 0;
+    let mut fps = 0.0;
+    // Index of the frame the window title was last set for
+    let mut titled = None;
+    while playback.is_running() {
+        // Hold the current frame on screen until unpaused or stepped
+        if playback.is_paused() && !playback.take_step() {
+            // The schedule starts over once playback resumes
+            deadline = None;
+            if let Some(audio) = &pacing.audio {
+                audio.pause();
+            }
+            thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+        // The producer hangs up once the last frame has been handed over. One that
+        // stalls is waited for with a note below the frame, counting up the seconds
+        let mut waited = Duration::ZERO;
+        let ready = loop {
+            let wait = match waited.is_zero() {
+                true => pacing.idle,
+                false => Duration::from_secs(1),
+            };
+            match consumer.next_frame(wait) {
+                Ok(ready) => break Some(ready),
+                Err(RecvTimeoutError::Disconnected) => break None,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+            waited += wait;
+            if pacing.exit_on_idle {
+                return Err(Stalled(waited).into());
+            }
+            if !playback.is_running() {
+                break None;
+            }
+            let (x, y) = front.origin;
+            write!(
+                out,
+                "\x1b[{};{}H\x1b[0m\x1b[2Kwaiting for frames, {} s",
+                y + front.height + 1,
+                x + 1,
+                waited.as_secs()
+            )?;
+            queue!(out, crossterm::cursor::MoveTo(0, 0))?;
+            let written = sink.write_frame(&out);
+            out.clear();
+            match written {
+                Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => break None,
+                result => result?,
+            }
+        };
+        let Some(ready) = ready else {
+            break;
+        };
+        // Time spent waiting doesn't put playback behind, the schedule starts over
+        if waited > Duration::ZERO {
+            deadline = None;
+        }
+        // Counting the one just taken
+        let ahead = playback.ready.fetch_sub(1, Ordering::Relaxed);
+        clear |= ready.clear;
+        // Frames converted before a seek are stale
+        if ready.epoch != playback.epoch() {
+            if playback.is_paused() {
+                playback.step();
+            }
+            consumer.give_back(ready);
+            continue;
+        }
+        let frame_time = ready.delay.unwrap_or(pacing.frame_time);
+        playback.stats.ahead(ahead);
+        playback.shown.store(ready.index, Ordering::Relaxed);
+        shown += 1;
+        // Swap the buffers
+        let spent = std::mem::replace(&mut front, ready);
+        if std::mem::take(&mut clear) {
+            // Clearing fills the screen with the current background color, which
+            // the last half block left set
+            queue!(
+                out,
+                crossterm::style::ResetColor,
+                crossterm::terminal::Clear(crossterm::terminal::ClearType::All)
+            )?;
+        }
+        out.extend_from_slice(&front.data);
+        let window = window_start.elapsed();
+        if window >= Duration::from_secs(1) {
+            fps = window_frames as f64 / window.as_secs_f64();
+            window_start = Instant::now();
+            window_frames = 0;
+        }
+        window_frames += 1;
+        // On the row below the frame, which fitting the frame kept free. Without the
+        // status line, a note that the frame was late is wiped off it
+        if waited > Duration::ZERO && !playback.shows_status() {
+            let (x, y) = front.origin;
+            write!(
+                out,
+                "\x1b[{};{}H\x1b[0m\x1b[2K",
+                y + front.height + 1,
+                x + 1
+            )?;
+        }
+        if playback.shows_status() {
+            let (x, y) = front.origin;
+            write!(
+                out,
+                "\x1b[{};{}H\x1b[0m\x1b[2Kframe {}",
+                y + front.height + 1,
+                x + 1,
+                front.index + 1
+            )?;
+            // A stream doesn't know how long it is
+            if frame_count != usize::MAX {
+                write!(out, "/{}", frame_count)?;
+            }
+            write!(out, "  {:.1} fps", fps)?;
+            if let Some(typed) = playback.typed() {
+                write!(out, "  go to {}", typed)?;
+            }
+        }
+        // Set anew only when the frame does change, a still image keeps its title
+        if let Some(titles) = pacing
+            .titles
+            .as_ref()
+            .filter(|_| titled != Some(front.index))
+        {
+            titled = Some(front.index);
+            let name = &titles[front.index.min(titles.len() - 1)];
+            write!(out, "\x1b]0;{} - frame {}", name, front.index + 1)?;
+            if frame_count != usize::MAX {
+                write!(out, "/{}", frame_count)?;
+            }
+            out.push(b'\x07');
+        }
+        // Reset the cursor position
+        queue!(out, crossterm::cursor::MoveTo(0, 0))?;
+        let written = playback.stats.time(Stage::Write, || sink.write_frame(&out));
+        match written {
+            // Whatever was showing the frames has gone away, playback ends with it
+            Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => break,
+            result => result?,
+        }
+        out.clear();
+        if pacing.uncapped {
+            consumer.give_back(spent);
+            continue;
+        }
+        if let Some(audio) = &pacing.audio {
+            // Frames sit on the track at --fps
+            let at = pacing.frame_time * front.index as u32;
+            if synced.is_none_or(|(epoch, index)| epoch != front.epoch || front.index < index) {
+                audio.seek(at);
+            }
+            synced = Some((front.epoch, front.index));
+            // The sound stays paused along with a frame stepped to
+            if playback.is_paused() {
+                consumer.give_back(spent);
+                continue;
+            }
+            audio.play();
+            // Once the track has ended the clock takes over
+            if let Some(position) = audio.position() {
+                let due = at + pacing.frame_time;
+                if position < due {
+                    thread::sleep(due - position);
+                } else {
+                    // Frames that fell behind the sound are skipped, like behind the
+                    // clock
+                    let behind = (position - due).as_nanos() / pacing.frame_time.as_nanos();
+                    if pacing.skip && behind > 0 {
+                        playback.skip(behind as usize);
+                    }
+                }
+                deadline = None;
+                consumer.give_back(spent);
+                continue;
+            }
+        }
+        let now = Instant::now();
+        let due = next_deadline(deadline, now, frame_time);
+        if now < due {
+            thread::sleep(due - now);
+        }
+        deadline = Some(due);
+        // Skip whole frames in the source while behind schedule, so playback keeps
+        // to the wall clock. Without skipping, the frames behind are shown right away
+        // until playback has caught up
+        let behind = frames_behind(due, Instant::now(), frame_time);
+        if pacing.skip && behind > 0 {
+            playback.skip(behind as usize);
+            deadline = Some(due + frame_time * behind);
+        }
+        // Hand the previous frame back to the CPU thread to fill with the next one
+        consumer.give_back(spent);
+    }
+    // On a new line below the frame and its status line, scrolling up if the frame
+    // reaches the bottom, so the shell's prompt doesn't land on top of it
+    if pacing.keep_last && shown > 0 {
+        let bottom = front.origin.1 + front.height - 1 + playback.shows_status() as u32;
+        queue!(
+            out,
+            crossterm::style::ResetColor,
+            crossterm::cursor::MoveTo(0, bottom.min(u16::MAX as u32) as u16)
+        )?;
+        out.extend_from_slice(b"\r\n");
+        sink.write_frame(&out)?;
+    }
+    Ok(shown)
+}
+
+/// Name of each frame for the window title: the name of its file for image files,
+/// that of `path` for every frame otherwise. Control characters are left out, they
+/// would end the title early.
+fn frame_names(frames: &Source, path: &Path) -> Vec<String> {
+    let name = |path: &Path| {
+        path.file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .chars()
+            .filter(|c| !c.is_control())
+            .collect()
+    };
+    match frames.paths() {
+        Some(paths) => paths.iter().map(|path| name(path)).collect(),
+        None => vec![name(path)],
+    }
+}
+
+/// Whether the terminal on stdout acts on escape sequences. Windows consoles only do
+/// once virtual terminal processing is switched on, which crossterm tries here; the
+/// consoles of Windows before 10 have none, and show the escapes as text.
+#[cfg(windows)]
+fn terminal_escapes() -> bool {
+    crossterm::ansi_support::supports_ansi()
+}
+
+#[cfg(not(windows))]
+fn terminal_escapes() -> bool {
+    true
+}
+
+/// Prints the terminal's capabilities as they're detected, and what the variables
+/// they're detected from are set to, followed by the formats that can be opened.
+fn print_capabilities() {
+    let row = |label: &str, value: &str| println!("{label:<14}{value}");
+    let name = |value: Option<clap::builder::PossibleValue>| {
+        value.map_or(String::new(), |value| value.get_name().to_owned())
+    };
+    let stdout = match std::io::stdout().is_terminal() {
+        true => "a terminal",
+        false => "redirected, frames are written as text without colors",
+    };
+    row("stdout", stdout);
+    let size = match crossterm::terminal::size() {
+        Ok((cols, rows)) => {
+            let (width, height) = cell_size();
+            format!("{cols}x{rows} cells of about {width}x{height} pixels")
+        }
+        Err(_) => "unknown".to_owned(),
+    };
+    row("size", &size);
+    let escapes = match terminal_escapes() {
+        true => "understood",
+        false => "not understood, frames are written as plain text",
+    };
+    row("escapes", escapes);
+    for var in [
+        "TERM",
+        "COLORTERM",
+        "TERM_PROGRAM",
+        "NO_COLOR",
+        "WT_SESSION",
+    ] {
+        let value = std::env::var(var).unwrap_or_else(|_| "(unset)".to_owned());
+        row(&format!("${var}"), &value);
+    }
+    let var = |name| std::env::var(name).unwrap_or_default();
+    let advertised = terminal_caps(&var("COLORTERM"), &var("TERM"));
+    row("advertised", &name(advertised.to_possible_value()));
+    row("colors", &name(ColorMode::detect().to_possible_value()));
+    let protocol = Protocol::detect().map(|protocol| name(protocol.to_possible_value()));
+    row(
+        "graphics",
+        protocol
+            .as_deref()
+            .unwrap_or("none known, frames are drawn with glyphs"),
+    );
+    let images = ImageFormat::all()
+        .filter(|format| format.reading_enabled())
+        .flat_map(|format| format.extensions_str().iter().copied())
+        .collect::<Vec<_>>();
+    row("images", &images.join(", "));
+    let ffmpeg = match Command::new("ffmpeg").arg("-version").output() {
+        Ok(_) => "through ffmpeg",
+        Err(_) => "once ffmpeg is on PATH",
+    };
+    row(
+        "videos",
+        &format!("{}, {}", VIDEO_EXTENSIONS.join(", "), ffmpeg),
+    );
+}
+
+/// Pixels of a terminal cell, as the terminal reports them through its window size.
+/// Terminals that don't are taken to have cells of a common size.
+fn cell_size() -> (u32, u32) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.columns > 0 && size.rows > 0 && size.width > 0 && size.height > 0 => (
+            (size.width / size.columns).max(1) as u32,
+            (size.height / size.rows).max(1) as u32,
+        ),
+        _ => (10, 20),
+    }
+}
+
+/// Rows kept free around the frame: the bottom one for the status line when it's
+/// shown, which is drawn right below the frame.
+fn layout(status: bool) -> Layout {
+    Layout {
+        top: 0,
+        bottom: status as u16,
+    }
+}
+
+/// Places a frame of `size` cells on a `cols`x`rows` terminal by `align`, within the
+/// rows `layout` leaves it.
+fn place(
+    size: (u32, u32),
+    terminal: (u16, u16),
+    layout: Layout,
+    align: Align,
+    options: &RenderOptions,
+) -> Placement {
+    Placement {
+        size,
+        origin: layout.origin(align, size, terminal, options),
+    }
+}
+
+/// Writes every frame as lines of text to `sink`, one after another with an empty
+/// line in between when they go into one stream, without touching the terminal.
+/// Sequences that would loop forever are written once. Unless `strict`, a frame that
+/// fails to decode is written as the one before it, or left out when it's the first.
+fn write_output(
+    mut frames: Source,
+    (width, height): (u32, u32),
+    loops: Option<u32>,
+    options: &RenderOptions,
+    strict: bool,
+    stats: &Stats,
+    sink: &mut dyn OutputSink,
+) -> anyhow::Result<()> {
+    let mut frame = FrameData::new(width, height);
+    let canvas = frames.dimensions()?;
+    let mut buf = Vec::new();
+    let mut written = false;
+    for pass in 0..loops.unwrap_or(1) {
+        for index in 0..frames.len() {
+            buf.clear();
+            match stats.time(Stage::Decode, || frames.frame(index)) {
+                Ok(Some(img)) => stats.time(Stage::Convert, || {
+                    let img = letterbox(&img, canvas, options.filter);
+                    render_frame(&img, &mut frame, None, options)
+                }),
+                Ok(None) => break,
+                Err(err) if strict => return Err(err),
+                Err(err) => {
+                    if pass == 0 {
+                        let instead = match written {
+                            true => "writing the frame before in its place",
+                            false => "leaving it out",
+                        };
+                        eprintln!("warning: {}: {}, {}", err, err.root_cause(), instead);
+                    }
+                    if !written {
+                        continue;
+                    }
+                }
+            }
+            if written && sink.is_stream() {
+                buf.push(b'\n');
+            }
+            written = true;
+            write_lines(&mut buf, &frame, options);
+            match stats.time(Stage::Write, || sink.write_frame(&buf)) {
+                // Piped into something like `head` that has seen enough
+                Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+                result => result?,
+            }
+        }
+    }
+    match sink.finish() {
+        Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// Frames of `frames` spread evenly from the first to the last, as many as fill a
+/// `columns`x`rows` grid or all of them when there aren't that many, each converted
+/// to `size` and laid out on a contact sheet. A frame that fails to decode is left
+/// out with a warning, unless `strict`.
+fn contact_sheet(
+    frames: &mut Source,
+    (columns, rows): (u32, u32),
+    size: (u32, u32),
+    options: &RenderOptions,
+    strict: bool,
+) -> anyhow::Result<FrameData> {
+    let (len, count) = (frames.len(), columns as usize * rows as usize);
+    let indices = match (len <= count, count) {
+        (true, _) => (0..len).collect::<Vec<_>>(),
+        (false, 1) => vec![0],
+        (false, _) => (0..count).map(|i| i * (len - 1) / (count - 1)).collect(),
+    };
+    let canvas = frames.dimensions()?;
+    let mut tiles = Vec::with_capacity(indices.len());
+    for index in indices {
+        let img = match frames.frame(index) {
+            Ok(Some(img)) => img,
+            Ok(None) => break,
+            Err(err) if strict => return Err(err),
+            Err(err) => {
+                eprintln!("warning: {}: {}, leaving it out", err, err.root_cause());
+                continue;
+            }
+        };
+        let mut tile = FrameData::new(size.0, size.1);
+        render_frame(
+            &letterbox(&img, canvas, options.filter),
+            &mut tile,
+            None,
+            options,
+        );
+        tiles.push(tile);
+    }
+    if tiles.is_empty() {
+        return Err(
+            NoFrames("none of the frames for the contact sheet could be decoded".into()).into(),
+        );
+    }
+    montage(&tiles, (columns, rows))
+}
+
+/// What the player exits with when it gives up, so a script can tell why. A clean
+/// quit, or the end of the frames, exits with 0 and arguments clap turns down with 2.
+#[derive(Clone, Copy)]
+enum Exit {
+    /// Anything not told apart below
+    Error = 1,
+    /// A frame failed to decode, which only ends playback with --strict
+    Decode = 3,
+    /// There were no frames to play
+    NoFrames = 4,
+    /// The terminal couldn't be switched into playback mode or asked its size
+    Terminal = 5,
+    /// No frame arrived in time with --exit-on-idle
+    Idle = 6,
+}
+
+impl Exit {
+    fn of(err: &anyhow::Error) -> Exit {
+        if err.downcast_ref::<NoFrames>().is_some() {
+            Exit::NoFrames
+        } else if err.downcast_ref::<DecodeError>().is_some() {
+            Exit::Decode
+        } else if err.downcast_ref::<TerminalError>().is_some() {
+            Exit::Terminal
+        } else if err.downcast_ref::<Stalled>().is_some() {
+            Exit::Idle
+        } else {
+            Exit::Error
+        }
+    }
+}
+
+/// The error of playback given up on with --exit-on-idle, after waiting this long
+/// for a frame.
+#[derive(Debug)]
+struct Stalled(Duration);
+
+impl std::fmt::Display for Stalled {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "no frame arrived in {} s", self.0.as_secs())
+    }
+}
+
+impl std::error::Error for Stalled {}
+
+/// The context of an error setting up the terminal to play on.
+#[derive(Debug)]
+struct TerminalError;
+
+impl std::fmt::Display for TerminalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("failed to set up the terminal")
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        // Printed the way returning the error from main would
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            ExitCode::from(Exit::of(&err) as u8)
+        }
+    }
+}
+
+fn run() -> anyhow::Result<()> {
+    let args = parse_args()?;
+    if args.list_formats {
+        print_capabilities();
+        return Ok(());
+    }
+    // Decoding and conversion run on a pool of their own rather than rayon's global
+    // one, so --threads caps all of it
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.threads.map_or(0, |threads| threads as usize))
+        .thread_name(|index| format!("convert-{index}"))
+        .build()?;
+    let path = match (&args.path, &args.playlist) {
+        (Some(path), _) | (None, Some(path)) => path.as_path(),
+        (None, None) => unreachable!("clap asks for one of them"),
+    };
+    let raw = match &args.raw_info {
+        Some(info) => {
+            let info = RawInfo::load(info)?;
+            Some(((info.width, info.height), info.format))
+        }
+        None => args.raw.map(|size| (size, args.raw_format)),
+    };
+    let (mut frames, loops) = match raw {
+        _ if args.playlist.is_some() => open_playlist(path, args.strict)?,
+        Some((size, format)) => (open_raw(path, size, format)?, None),
+        None => open_source(path, args.fps, &args.formats)?,
+    };
+    frames = frames.auto_rotate(!args.no_auto_rotate);
+    // Worked out while the frames before --start are still there to add up
+    let index_label = args.burn_index.then(|| {
+        let number = args.start.unwrap_or(1).min(frames.len() as u64).max(1);
+        let shown_at = (0..number as usize - 1)
+            .map(|i| frames.delay(i).unwrap_or(Duration::from_secs(1) / args.fps))
+            .sum::<Duration>();
+        format!(
+            "frame {}  {:02}:{:02}.{:03}",
+            number,
+            shown_at.as_secs() / 60,
+            shown_at.as_secs() % 60,
+            shown_at.subsec_millis()
+        )
+    });
+    if args.start.is_some() || args.end.is_some() {
+        let len = frames.len() as u64;
+        let (start, end) = (args.start.unwrap_or(1), args.end.unwrap_or(len));
+        let problem = if start.max(end) > len {
+            Some(format!(
+                "--{} {} is past the last frame of {}, which has {}",
+                if end > len { "end" } else { "start" },
+                start.max(end),
+                path.display(),
+                len
+            ))
+        } else {
+            (start > end).then(|| format!("--start {} comes after --end {}", start, end))
+        };
+        if let Some(problem) = problem {
+            Args::command()
+                .error(ErrorKind::ValueValidation, problem)
+                .exit();
+        }
+        frames = frames.slice(start as usize - 1..end as usize)?;
+    }
+    if args.once {
+        frames = frames.slice(0..1)?;
+    }
+    // Named while the files are still at hand, before they go to be read ahead
+    let titles = args.title.then(|| frame_names(&frames, path));
+    if let Some(capacity) = args.cache {
+        frames = pool.install(|| frames.cached(capacity, args.strict))?;
+    }
+    let loops = match (args.no_loop || args.once, args.loop_count) {
+        (true, _) => Some(1),
+        (false, Some(0)) => None,
+        (false, Some(loops)) => Some(loops),
+        (false, None) => loops,
+    };
+    let frame_count = frames.len();
+    let rewinds = frames.rewinds();
+    let mut source = frames.dimensions()?;
+    // The frames are zoomed into within their full size
+    let canvas = source;
+    if let Some((x, y, width, height)) = args.crop {
+        if x as u64 + width as u64 > source.0 as u64 || y as u64 + height as u64 > source.1 as u64 {
+            Args::command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    format!(
+                        "--crop {},{},{},{} reaches past the {}x{} frames of {}",
+                        x,
+                        y,
+                        width,
+                        height,
+                        source.0,
+                        source.1,
+                        path.display()
+                    ),
+                )
+                .exit();
+        }
+        // The frame is fitted to the region rather than the whole source
+        source = (width, height);
+    }
+    // A console that shows escape sequences as they are can't be played on, it's
+    // given the frames as plain text instead
+    let escapes = !std::io::stdout().is_terminal() || terminal_escapes();
+    if !escapes {
+        eprintln!(
+            "warning: this console doesn't understand escape sequences, the frames are \
+             written as plain text; Windows Terminal or the console of Windows 10 and \
+             later plays them"
+        );
+    }
+    // Without a terminal to play on the frames are written out as text, an exported
+    // image or a single frame doesn't need one either
+    let to_text = args.output.is_some()
+        || args.dump_dir.is_some()
+        || args.once
+        || args.montage.is_some()
+        || args.export_image.is_some()
+        || !std::io::stdout().is_terminal()
+        || !escapes;
+    let ramp = match &args.ramp_file {
+        Some(path) => {
+            let glyphs = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            // The line break an editor leaves at the end isn't part of the ramp
+            let glyphs = glyphs.trim_end_matches(['\n', '\r']);
+            if glyphs.is_empty() {
+                bail!("{} holds no glyphs", path.display());
+            }
+            Ramp::Custom(glyphs.chars().collect::<Vec<_>>().leak())
+        }
+        None => args.ramp,
+    };
+    let to_stdout = args.export_image.is_none()
+        && args.dump_dir.is_none()
+        && args
+            .output
+            .as_deref()
+            .is_none_or(|path| path == Path::new("-"));
+    let to_terminal = to_stdout && std::io::stdout().is_terminal() && escapes;
+    let color = match args.color {
+        _ if to_stdout && !escapes => ColorMode::Mono,
+        _ if args.force_truecolor => ColorMode::Truecolor,
+        Some(color) => color,
+        // Text going down a pipe or into a log is left plain unless asked for, so it
+        // can be searched and pasted as it is
+        None if to_stdout && !to_terminal => ColorMode::Mono,
+        None => ColorMode::detect(),
+    };
+    let mut builder = RenderOptions::builder()
+        .ramp(ramp)
+        .color_mode(color)
+        .filter(args.filter)
+        .sample(args.sample)
+        .cell_aspect(args.cell_aspect)
+        .braille(args.braille)
+        .half_block(args.half_block)
+        .dither(args.dither)
+        .edges(args.edges)
+        .diff(args.diff)
+        .gamma(args.gamma)
+        .lab(args.lab)
+        .contrast(args.contrast)
+        .brightness(args.brightness)
+        .invert(args.invert)
+        .seed(args.seed);
+    if let Some(columns) = args.pixel_width {
+        builder = builder.pixel_width(columns);
+    }
+    if let Some(threshold) = args.edge_threshold {
+        builder = builder.edge_threshold(threshold);
+    }
+    if let Some(luma) = args.luma {
+        builder = builder.luma(luma);
+    }
+    // The levels per channel that give at most that many colors, 256 of them are all
+    // there are
+    if let Some(levels) = args
+        .colors
+        .map(|colors| {
+            (2..=256u32)
+                .rev()
+                .find(|levels| levels.pow(3) <= colors)
+                .unwrap()
+        })
+        .filter(|&levels| levels < 256)
+    {
+        builder = builder.color_levels(levels as u8);
+    }
+    if let Some(bits) = args.posterize {
+        builder = builder.posterize(bits);
+    }
+    if let Some(color) = args.bg {
+        builder = builder.background(color);
+    }
+    if let Some(rows) = args.block_rows {
+        builder = builder.block_rows(rows);
+    }
+    // Pictures only go to a terminal, text output keeps to glyphs
+    if let Some(protocol) = args
+        .protocol
+        .filter(|_| to_terminal)
+        .and_then(ProtocolChoice::protocol)
+    {
+        builder = builder.graphics(Graphics {
+            protocol,
+            cell_size: cell_size(),
+        });
+    }
+    if let Some(crop) = args.crop {
+        builder = builder.crop(crop);
+    }
+    // The builder checks the values given, from the command line or the config file
+    let options = builder.build().unwrap_or_else(|err| {
+        Args::command()
+            .error(ErrorKind::ValueValidation, err)
+            .exit()
+    });
+    // A sixel picture leaves the cursor on the row below it, which has to be kept
+    // free like for the status line or the screen scrolls
+    let sixel = matches!(
+        options.graphics,
+        Some(Graphics {
+            protocol: Protocol::Sixel,
+            ..
+        })
+    );
+    // Asking for truecolor overrides the detection, but on a terminal that doesn't
+    // advertise it the colors likely come out wrong. $NO_COLOR says nothing about that
+    let var = |name| std::env::var(name).unwrap_or_default();
+    if to_terminal
+        && matches!(args.color, Some(ColorMode::Truecolor))
+        && !matches!(
+            terminal_caps(&var("COLORTERM"), &var("TERM")),
+            ColorMode::Truecolor
+        )
+        && std::env::var_os("WT_SESSION").is_none()
+    {
+        eprintln!(
+            "warning: the terminal doesn't advertise 24-bit colors through $COLORTERM or $TERM, \
+             they may show wrong or not at all; --color ansi256 suits it better, \
+             --force-truecolor skips this check"
+        );
+    }
+    if !options.braille && !options.half_block && !options.edges {
+        let undrawable = ramp.undrawable(options.cell_columns());
+        if !undrawable.is_empty() {
+            eprintln!(
+                "warning: the ramp glyphs {:?} don't fit a cell {} columns wide and show as spaces",
+                undrawable,
+                options.cell_columns()
+            );
+        }
+    }
+    let (width, height) = match args.scale {
+        Some(scale) => {
+            let shape = options.cell_shape(source);
+            let (width, height) = (shape.0 / scale, shape.1 / scale);
+            if width == 0 || height == 0 {
+                Args::command()
+                    .error(
+                        ErrorKind::ValueValidation,
+                        format!(
+                            "--scale {} shrinks the {}x{} source to {}x{}, use a scale of at most {}",
+                            scale,
+                            source.0,
+                            source.1,
+                            width,
+                            height,
+                            shape.0.min(shape.1)
+                        ),
+                    )
+                    .exit();
+            }
+            // Size of the frame in cells rather than pixels
+            let (cell_width, cell_height) = options.cell_pixels();
+            (width.div_ceil(cell_width), height.div_ceil(cell_height))
+        }
+        None => {
+            // Text output still fits the terminal it's run from, when there is one
+            let size = match crossterm::terminal::size() {
+                Ok(size) => size,
+                Err(_) if to_text => (80, 24),
+                Err(err) => return Err(anyhow::Error::new(err).context(TerminalError)),
+            };
+            // Each frame of a contact sheet gets its share of the terminal, less the
+            // blank line between them
+            let size = match args.montage {
+                Some((columns, rows)) => {
+                    let share = |room: u16, count: u32| {
+                        ((room as u32 + 1) / count).saturating_sub(1).max(1) as u16
+                    };
+                    (share(size.0, columns), share(size.1, rows))
+                }
+                None => size,
+            };
+            layout((args.status || sixel) && !to_text).fit(source, size, &options)
+        }
+    };
+    let sheet = match args.montage {
+        Some(grid) => Some(pool.install(|| {
+            contact_sheet(&mut frames, grid, (width, height), &options, args.strict)
+        })?),
+        None => None,
+    };
+    if let Some(path) = &args.export_image {
+        let font = match &args.font {
+            Some(font) => {
+                let data = std::fs::read(font)
+                    .with_context(|| format!("failed to read {}", font.display()))?;
+                FontArc::try_from_vec(data)
+                    .with_context(|| format!("{} is not a font", font.display()))?
+            }
+            None => FontArc::new(default_font()),
+        };
+        let frame = match sheet {
+            Some(sheet) => sheet,
+            None => {
+                let Some(img) = frames.frame(0)? else {
+                    return Err(NoFrames(format!(
+                        "{} ended before its first frame",
+                        path.display()
+                    ))
+                    .into());
+                };
+                let mut frame = FrameData::new(width, height);
+                pool.install(|| render_frame(&img, &mut frame, None, &options));
+                frame
+            }
+        };
+        let mut picture = rasterize(&frame, &options, &font, args.font_size);
+        if let Some(label) = &index_label {
+            burn_label(&mut picture, label, &font, args.font_size);
+        }
+        return picture
+            .save(path)
+            .with_context(|| format!("failed to write {}", path.display()));
+    }
+    if let Some(sheet) = sheet {
+        let mut buf = Vec::new();
+        write_lines(&mut buf, &sheet, &options);
+        return match args.output.as_deref() {
+            Some(path) if path != Path::new("-") => std::fs::write(path, buf)
+                .with_context(|| format!("failed to write {}", path.display())),
+            _ => match std::io::stdout().lock().write_all(&buf) {
+                Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+                result => Ok(result?),
+            },
+        };
+    }
+    if to_text {
+        let stats = Stats::new(args.stats);
+        let written = pool.install(|| match args.output.as_deref() {
+            _ if args.dump_dir.is_some() => {
+                let dir = args.dump_dir.as_deref().unwrap();
+                // Streams don't say how many frames they hold
+                let count = frames
+                    .rewinds()
+                    .then(|| frames.len() * loops.unwrap_or(1) as usize);
+                let mut sink = DirSink::new(dir, count)
+                    .with_context(|| format!("failed to create {}", dir.display()))?;
+                write_output(
+                    frames,
+                    (width, height),
+                    loops,
+                    &options,
+                    args.strict,
+                    &stats,
+                    &mut sink,
+                )
+            }
+            Some(path) if path != Path::new("-") => {
+                let file = File::create(path)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                write_output(
+                    frames,
+                    (width, height),
+                    loops,
+                    &options,
+                    args.strict,
+                    &stats,
+                    &mut FileSink::new(file),
+                )
+            }
+            _ => write_output(
+                frames,
+                (width, height),
+                loops,
+                &options,
+                args.strict,
+                &stats,
+                &mut FileSink::new(std::io::stdout().lock()),
+            ),
+        });
+        stats.print();
+        return written;
+    }
+    let frame_time = Duration::from_secs(1) / args.fps;
+    let sequencing = Sequencing {
+        loops,
+        transition: args
+            .transition
+            .map(|Transition::Crossfade| Duration::from_millis(args.transition_ms)),
+        dedupe: args.dedupe,
+    };
+    // The device stays open until playback is over. A clip starts its track as far in
+    // as its first frame
+    let (_output, audio) = match &args.audio {
+        Some(path) => {
+            let offset = frame_time * (args.start.unwrap_or(1) - 1) as u32;
+            let (output, audio) = Audio::open(path, offset)?;
+            (Some(output), Some(audio))
+        }
+        None => (None, None),
+    };
+    let idle = Duration::from_secs(args.idle_timeout);
+    let pacing = Pacing {
+        frame_time,
+        skip: !args.no_skip,
+        uncapped: args.uncapped,
+        audio,
+        keep_last: args.keep_last,
+        titles,
+        idle,
+        exit_on_idle: args.exit_on_idle,
+    };
+    // --no-draw goes through all of drawing but the writing
+    let mut sink: Box<dyn OutputSink + Send> = match args.no_draw {
+        true => Box::new(NullSink),
+        false => Box::new(TerminalSink::new()),
+    };
     let mut stdout = std::io::stdout();
-    enable_raw_mode().unwrap();
+    // Restore right away when a thread panics, waiting for the guard to drop would
+    // print the panic message onto the alternate screen
+    let default_hook = std::panic::take_hook();
+    let (alternate_screen, title) = (!args.keep_last, args.title);
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal(alternate_screen, title);
+        default_hook(info);
+    }));
+    let placement = place(
+        (width, height),
+        crossterm::terminal::size().context(TerminalError)?,
+        layout(args.status || sixel),
+        args.align,
+        &options,
+    );
+    let terminal = TerminalGuard::new(alternate_screen, title).context(TerminalError)?;
     execute!(
         stdout,
-        crossterm::terminal::Clear(crossterm::terminal::ClearType::All)
-    )
-    .unwrap();
-    execute!(stdout, crossterm::cursor::Hide).unwrap();
-    execute!(stdout, crossterm::cursor::MoveTo(0, 0)).unwrap();
-    let paths = get_path();
-    //let images = preload_images(&paths);
-    let double_buffer = Arc::new(DoubleBuffer::new(1920 / size, 1080 / size));
-    let (frame_ready_tx, frame_ready_rx) = channel();
-    let (new_request_tx, new_request_rx) = channel();
-    let db_cpu = Arc::clone(&double_buffer);
-    let cpu_handle = thread::spawn(move || loop {
-        for path in paths.iter() {
-            //for img in images.iter() {
-            let img = image::open(&path).unwrap();
-            // new_request_rx.recv().unwrap();
-            let back = db_cpu.temp_mut();
-            {
-                let cols = back.width;
-                let rows = back.height;
-                let chunk_rows = rows / 24;
-                let blocks = (0..24)
-                    .into_par_iter()
-                    .map(|block_id| {
-                        let start = block_id * chunk_rows;
-                        let end = start + chunk_rows;
-                        let mut buf = Vec::with_capacity(((end - start) * cols) as usize * 20);
-                        for y in start..end {
-                            for x in 0..cols {
-                                let [r, g, b, _] = img.get_pixel(x, y).0;
-                                write!(
-                                    &mut buf,
-                                    "\x1b[{};{}H\x1b[38;2;{};{};{}m{}",
-                                    y,
-                                    x * 2,
-                                    r,
-                                    g,
-                                    b,
-                                    "██"
-                                )
-                                .unwrap();
-                            }
-                        }
-                        buf
-                    })
-                    .collect::<Vec<Vec<u8>>>();
-
-                back.data.clear();
-                for row in blocks {
-                    back.data.extend(row);
-                }
-            }
-            new_request_rx.recv().unwrap();
-            db_cpu.temp_to_back();
-            frame_ready_tx.send(()).unwrap();
+        crossterm::terminal::Clear(crossterm::terminal::ClearType::All),
+        crossterm::cursor::MoveTo(0, 0)
+    )?;
+    // The render thread shows one buffer, and the CPU thread fills the --lookahead
+    // others in turn, which wait until they're due
+    let (producer, consumer) = handoff(args.lookahead as usize, (width, height));
+    let started = Instant::now();
+    let playback = Arc::new(Playback::new(args.status, args.strict, args.stats));
+    let cpu_playback = Arc::clone(&playback);
+    let cpu_handle = thread::spawn(move || {
+        let result = pool.install(|| {
+            convert_frames(
+                frames,
+                placement,
+                sequencing,
+                options,
+                &cpu_playback,
+                producer,
+            )
+        });
+        if result.is_err() {
+            cpu_playback.stop();
         }
+        result
     });
-    let db_render = Arc::clone(&double_buffer);
+    let render_playback = Arc::clone(&playback);
     let render_handle = thread::spawn(move || {
-        new_request_tx.send(()).unwrap();
-        // let mut write_buffer =
-        //     Vec::with_capacity((db_render.front().width * db_render.front().height * 20) as usize);
-        let mut stdout = std::io::stdout();
-        let frame_time = std::time::Duration::from_millis(1000 / 16);
-        let mut delay = std::time::Duration::ZERO;
-        loop {
-            if delay > frame_time {
-                frame_ready_rx.recv().unwrap();
-                new_request_tx.send(()).unwrap();
-                delay -= frame_time;
+        // Placed like the frames to come, for a note that the first one is late
+        let mut front = FrameData::new(width, height);
+        front.origin = placement.origin;
+        let result = render_frames(
+            front,
+            frame_count,
+            pacing,
+            &render_playback,
+            &mut *sink,
+            consumer,
+        );
+        // Playback is over, stop polling for input
+        render_playback.stop();
+        result
+    });
+    // Converts the frame on screen anew, or the one after it during playback
+    let show_again = || {
+        let shown = playback.shown.load(Ordering::Relaxed);
+        if playback.is_paused() {
+            playback.seek(shown);
+        } else {
+            playback.seek((shown + 1) % frame_count);
+        }
+    };
+    // Refits the frame to a `cols`x`rows` terminal and redraws it from scratch
+    let redraw = |(cols, rows): (u16, u16)| {
+        // A fixed --scale keeps its size, but the screen still needs a redraw
+        let layout = layout(playback.shows_status() || sixel);
+        let size = match args.scale {
+            Some(_) => (width, height),
+            None => layout.fit(source, (cols, rows), &options),
+        };
+        playback.resize(place(size, (cols, rows), layout, args.align, &options));
+        show_again();
+    };
+    // Shows frame `index` next, or the last one past the end
+    let jump = |index: usize| playback.seek(index.min(frame_count - 1));
+    // A tenth of the sequence, what the arrow keys jump by during playback
+    let tenth = (frame_count / 10).max(1);
+    let mut zoom = Zoom::new(args.crop, canvas);
+    while playback.is_running() {
+        if !event::poll(Duration::from_millis(50))? {
+            continue;
+        }
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            Event::Resize(cols, rows) => {
+                redraw((cols, rows));
                 continue;
             }
-            let now = std::time::Instant::now();
-            frame_ready_rx.recv().unwrap();
-            {
-                let front = db_render.front();
-                stdout.write_all(&front.data).unwrap();
-            }
-            // Reset the cursor position
-            execute!(stdout, crossterm::cursor::MoveTo(0, 0)).unwrap();
-            let elapsed = now.elapsed();
-            delay += elapsed;
-            // 16 FPS
-            if delay < frame_time {
-                thread::sleep(frame_time - delay);
-                delay = std::time::Duration::ZERO;
-            } else {
-                delay -= frame_time;
-            }
-            // Swap the buffers
-            double_buffer.swap();
-            // Notify the CPU thread that needs to process the new frame
-            new_request_tx.send(()).unwrap();
+            _ => continue,
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
         }
-    });
-    // Wait for the threads to finish
-    cpu_handle.join().unwrap();
-    render_handle.join().unwrap();
-
-    disable_raw_mode().unwrap();
-    execute!(stdout, LeaveAlternateScreen).unwrap();
-    let (weight, height) = crossterm::terminal::size().unwrap();
-    print!("{} {}", height, weight);
+        if is_quit(key) {
+            playback.stop();
+            continue;
+        }
+        // Digits make up a frame number for `g`, any other key drops them. Streams
+        // can't seek
+        if let KeyCode::Char(digit @ '0'..='9') = key.code {
+            if rewinds {
+                playback.type_digit(digit.to_digit(10).unwrap());
+                // A paused player redraws the frame to update the status line
+                if playback.is_paused() && playback.shows_status() {
+                    jump(playback.shown.load(Ordering::Relaxed));
+                }
+            }
+            continue;
+        }
+        let typed = playback.take_typed();
+        let shown = playback.shown.load(Ordering::Relaxed);
+        // Zoomed in, the arrow keys pan rather than going through the frames
+        let moved = match key.code {
+            KeyCode::Char('+' | '=') => Some(zoom.zoom(1)),
+            KeyCode::Char('-') => Some(zoom.zoom(-1)),
+            KeyCode::Left if zoom.is_zoomed() => Some(zoom.pan((-1, 0))),
+            KeyCode::Right if zoom.is_zoomed() => Some(zoom.pan((1, 0))),
+            KeyCode::Up if zoom.is_zoomed() => Some(zoom.pan((0, -1))),
+            KeyCode::Down if zoom.is_zoomed() => Some(zoom.pan((0, 1))),
+            _ => None,
+        };
+        if let Some(moved) = moved {
+            if moved {
+                playback.view(zoom.region());
+                show_again();
+            }
+            continue;
+        }
+        match key.code {
+            KeyCode::Char(' ') => playback.toggle_pause(),
+            KeyCode::Char('s') => {
+                playback.toggle_status();
+                redraw(crossterm::terminal::size()?);
+            }
+            // Frame numbers count from 1 like on the status line, `g` alone goes to
+            // the first
+            KeyCode::Char('g') | KeyCode::Enter if rewinds => {
+                jump(typed.unwrap_or(1).saturating_sub(1))
+            }
+            KeyCode::Right if playback.is_paused() => playback.step(),
+            // Stepping back re-decodes the earlier frame from disk
+            KeyCode::Left if playback.is_paused() && rewinds => {
+                playback.seek((shown + frame_count - 1) % frame_count);
+            }
+            KeyCode::Right if rewinds => jump(shown.saturating_add(tenth)),
+            KeyCode::Left if rewinds => jump(shown.saturating_sub(tenth)),
+            _ => {}
+        }
+    }
+    // Wait for the threads to finish. A CPU thread stuck reading a stream that's
+    // stalled is left to it, once it's had as long as a frame may take
+    let rendered = render_handle
+        .join()
+        .map_err(|_| anyhow!("the render thread panicked"))?;
+    let stopped = Instant::now();
+    while !cpu_handle.is_finished() && stopped.elapsed() < idle {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let converted = match cpu_handle.is_finished() {
+        true => cpu_handle
+            .join()
+            .map_err(|_| anyhow!("the CPU thread panicked"))?,
+        false => Ok(()),
+    };
+    drop(terminal);
+    for (_, err) in playback.failed.lock().unwrap().iter() {
+        eprintln!(
+            "warning: {}: {}, the frame before was shown in its place",
+            err,
+            err.root_cause()
+        );
+    }
+    let dropped = playback.dropped.load(Ordering::Relaxed);
+    if dropped > 0 {
+        eprintln!("skipped {} frames to keep up", dropped);
+    }
+    playback.stats.print();
+    let shown = converted.and(rendered)?;
+    if args.uncapped {
+        let elapsed = started.elapsed();
+        eprintln!(
+            "{} frames in {:.2} s, {:.1} fps",
+            shown,
+            elapsed.as_secs_f64(),
+            shown as f64 / elapsed.as_secs_f64()
+        );
+    }
+    Ok(())
 }
@@ -0,0 +1,132 @@
+//! Where drawn frames go: the terminal, a file, a file each, or nowhere. The player
+//! and the text output hand each frame over in one piece, and the sink decides how
+//! it's written.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Stdout, Write},
+    path::{Path, PathBuf},
+};
+
+/// Takes the bytes of each frame as they are drawn.
+pub trait OutputSink {
+    /// Writes everything drawing one frame. The bytes are only borrowed, so a frame
+    /// that reuses its buffer doesn't allocate.
+    fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()>;
+
+    /// Writes out whatever is still buffered, once the last frame is in.
+    fn finish(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Whether the frames all end up one after another in the same stream, rather
+    /// than each on its own.
+    fn is_stream(&self) -> bool {
+        true
+    }
+}
+
+/// The terminal the player runs on. Each frame is flushed right away so it shows
+/// when it's due rather than when a buffer fills.
+pub struct TerminalSink {
+    stdout: Stdout,
+}
+
+impl TerminalSink {
+    pub fn new() -> Self {
+        TerminalSink {
+            stdout: std::io::stdout(),
+        }
+    }
+}
+
+impl OutputSink for TerminalSink {
+    fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        self.stdout.write_all(frame)?;
+        self.stdout.flush()
+    }
+}
+
+/// A file or pipe the frames are written to as text, buffered since nobody watches
+/// them arrive.
+pub struct FileSink<W: Write> {
+    out: BufWriter<W>,
+}
+
+impl<W: Write> FileSink<W> {
+    pub fn new(out: W) -> Self {
+        FileSink {
+            out: BufWriter::new(out),
+        }
+    }
+}
+
+impl<W: Write> OutputSink for FileSink<W> {
+    fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        self.out.write_all(frame)
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// A directory the frames are written into, each to a file of its own named by its
+/// number, like `007.ans`. Numbers are padded to the same width so the files sort
+/// in the order they were written, with six digits when the count isn't known.
+pub struct DirSink {
+    dir: PathBuf,
+    digits: usize,
+    next: usize,
+}
+
+impl DirSink {
+    /// Makes `dir` if it isn't there yet, for `count` frames.
+    pub fn new(dir: &Path, count: Option<usize>) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(DirSink {
+            dir: dir.to_path_buf(),
+            digits: count.map_or(6, |count| count.to_string().len()),
+            next: 1,
+        })
+    }
+}
+
+impl OutputSink for DirSink {
+    fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        let path = self
+            .dir
+            .join(format!("{:0digits$}.ans", self.next, digits = self.digits));
+        File::create(&path)
+            .and_then(|mut file| file.write_all(frame))
+            .map_err(|err| {
+                std::io::Error::new(
+                    err.kind(),
+                    format!("failed to write {}: {}", path.display(), err),
+                )
+            })?;
+        self.next += 1;
+        Ok(())
+    }
+
+    fn is_stream(&self) -> bool {
+        false
+    }
+}
+
+/// Drops every frame, for `--no-draw` to measure everything but the writing.
+pub struct NullSink;
+
+impl OutputSink for NullSink {
+    fn write_frame(&mut self, _frame: &[u8]) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Keeps the frames in memory, one after another.
+impl OutputSink for Vec<u8> {
+    fn write_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        self.extend_from_slice(frame);
+        Ok(())
+    }
+}
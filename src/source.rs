@@ -0,0 +1,977 @@
+//! Decoding of the frames to play, from image files, animated GIFs and WebPs, videos
+//! and raw streams.
+
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{HashMap, VecDeque},
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    ops::Range,
+    path::{Path, PathBuf},
+    process::{Child, ChildStdout, Command, Stdio},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+use clap::ValueEnum;
+use image::{
+    codecs::{gif::GifDecoder, webp::WebPDecoder},
+    metadata::Orientation,
+    AnimationDecoder, DynamicImage, GenericImageView, ImageDecoder, ImageFormat, ImageReader,
+    RgbImage, RgbaImage,
+};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::Deserialize;
+
+/// Where the frames come from.
+pub enum Source {
+    /// Image files decoded one at a time as they are shown
+    Files {
+        paths: Vec<PathBuf>,
+        /// Turn each image upright by the orientation in its EXIF data
+        auto_rotate: bool,
+    },
+    /// An animation decoded up front, each frame with its own delay
+    Animation(Vec<(DynamicImage, Option<Duration>)>),
+    /// A video streamed through ffmpeg
+    Video(Video),
+    /// Image files decoded ahead of time by a background thread
+    ReadAhead(ReadAhead),
+    /// Raw frames written by another program, until it stops
+    Stream(Stream),
+}
+
+impl Source {
+    /// Number of frames, `usize::MAX` for a stream that hasn't ended.
+    pub fn len(&self) -> usize {
+        match self {
+            Source::Files { paths, .. } => paths.len(),
+            Source::Animation(frames) => frames.len(),
+            Source::Video(video) => video.len,
+            Source::ReadAhead(cache) => cache.len,
+            Source::Stream(_) => usize::MAX,
+        }
+    }
+
+    /// Whether there are no frames, which `open_source` never returns.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Size of the frames in pixels, taken from the first one that can be read.
+    pub fn dimensions(&self) -> anyhow::Result<(u32, u32)> {
+        match self {
+            // A broken first frame shouldn't keep the rest from playing
+            Source::Files { paths, auto_rotate } => image_dimensions(&paths[0], *auto_rotate)
+                .with_context(|| DecodeError(paths[0].clone()))
+                .or_else(|err| {
+                    paths[1..]
+                        .iter()
+                        .find_map(|path| image_dimensions(path, *auto_rotate).ok())
+                        .ok_or(err)
+                }),
+            Source::Animation(frames) => Ok(frames[0].0.dimensions()),
+            Source::Video(video) => Ok(video.dimensions),
+            Source::ReadAhead(cache) => Ok(cache.dimensions),
+            Source::Stream(stream) => Ok(stream.dimensions),
+        }
+    }
+
+    /// Decodes frame `index`, or borrows it when it is already in memory. `None` once
+    /// a stream has ended before `index`.
+    pub fn frame(&mut self, index: usize) -> anyhow::Result<Option<Cow<'_, DynamicImage>>> {
+        match self {
+            Source::Files { paths, auto_rotate } => {
+                decode(&paths[index], *auto_rotate).map(|frame| Some(Cow::Owned(frame)))
+            }
+            Source::Animation(frames) => Ok(Some(Cow::Borrowed(&frames[index].0))),
+            Source::Video(video) => video.frame(index).map(|frame| Some(Cow::Owned(frame))),
+            Source::ReadAhead(cache) => cache.frame(index).map(|frame| Some(Cow::Owned(frame))),
+            Source::Stream(stream) => Ok(stream.frame(index)?.map(Cow::Borrowed)),
+        }
+    }
+
+    /// Image file each frame is read from, while they're still read one at a time.
+    /// `None` for other sources, and once the files are read ahead or cached.
+    pub fn paths(&self) -> Option<&[PathBuf]> {
+        match self {
+            Source::Files { paths, .. } => Some(paths),
+            _ => None,
+        }
+    }
+
+    /// Whether frames before the last one can be shown again, which a stream can't.
+    pub fn rewinds(&self) -> bool {
+        !matches!(self, Source::Stream(_))
+    }
+
+    /// How long frame `index` wants to stay on screen, `None` when the source
+    /// doesn't say.
+    pub fn delay(&self, index: usize) -> Option<Duration> {
+        match self {
+            Source::Files { .. } | Source::Video(_) | Source::ReadAhead(_) | Source::Stream(_) => {
+                None
+            }
+            Source::Animation(frames) => frames[index].1,
+        }
+    }
+}
+
+impl Source {
+    /// Cuts the frames down to those in `range`, which has to lie within the sequence.
+    /// Videos start decoding at the first frame of the range. Streams and frames
+    /// already being read ahead can't be cut, slice before calling `cached`.
+    pub fn slice(self, range: Range<usize>) -> anyhow::Result<Self> {
+        if range.start >= range.end || range.end > self.len() {
+            bail!(
+                "frames {}..{} are not within the {} frames of the sequence",
+                range.start,
+                range.end,
+                self.len()
+            );
+        }
+        Ok(match self {
+            Source::Files { paths, auto_rotate } => Source::Files {
+                paths: paths[range].to_vec(),
+                auto_rotate,
+            },
+            Source::Animation(mut frames) => {
+                frames.truncate(range.end);
+                frames.drain(..range.start);
+                Source::Animation(frames)
+            }
+            Source::Video(mut video) => {
+                video.first += range.start;
+                video.len = range.len();
+                Source::Video(video)
+            }
+            Source::ReadAhead(_) => bail!("frames read ahead can't be sliced"),
+            Source::Stream(_) => bail!("a stream can't be sliced"),
+        })
+    }
+
+    /// Keeps up to `capacity` decoded frames of an image sequence in memory ahead of
+    /// playback. A cache that holds every frame decodes them all up front instead,
+    /// leaving out with a warning those that fail to decode unless `strict`.
+    /// Animations are already in memory and videos stream, so they stay as they are.
+    pub fn cached(self, capacity: usize, strict: bool) -> anyhow::Result<Self> {
+        let Source::Files { paths, auto_rotate } = self else {
+            return Ok(self);
+        };
+        if capacity >= paths.len() {
+            let frames = preload_images(&paths, auto_rotate, strict)?;
+            return Ok(Source::Animation(
+                frames.into_iter().map(|frame| (frame, None)).collect(),
+            ));
+        }
+        Source::Files { paths, auto_rotate }.read_ahead(capacity)
+    }
+
+    /// Decodes up to `capacity` frames of an image sequence ahead of playback on a
+    /// thread of its own, so conversion doesn't wait on the disk or the decoder.
+    /// Other sources stay as they are: animations are already in memory, ffmpeg
+    /// decodes videos in a process of its own and streams come as they are written.
+    pub fn read_ahead(self, capacity: usize) -> anyhow::Result<Self> {
+        let Source::Files { paths, auto_rotate } = self else {
+            return Ok(self);
+        };
+        let dimensions = Source::Files {
+            paths: paths.clone(),
+            auto_rotate,
+        }
+        .dimensions()?;
+        Ok(Source::ReadAhead(ReadAhead::spawn(
+            paths,
+            auto_rotate,
+            dimensions,
+            capacity,
+        )))
+    }
+
+    /// Turns image files upright by the orientation in their EXIF data, as phone
+    /// cameras store photos taken in portrait, or leaves them as they're stored. They
+    /// are turned unless told otherwise here, before `cached` or `read_ahead`.
+    pub fn auto_rotate(self, auto_rotate: bool) -> Self {
+        match self {
+            Source::Files { paths, .. } => Source::Files { paths, auto_rotate },
+            source => source,
+        }
+    }
+}
+
+/// Decodes the frames following the one last asked for on a thread of its own, so
+/// disk I/O and decoding overlap with conversion.
+pub struct ReadAhead {
+    len: usize,
+    dimensions: (u32, u32),
+    shared: Arc<(Mutex<Window>, Condvar)>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+/// Frames decoded but not yet taken, in order.
+struct Window {
+    frames: VecDeque<(usize, anyhow::Result<DynamicImage>)>,
+    /// Index the decode thread works on next
+    next: usize,
+    /// Bumped when the window restarts elsewhere, so a decode in flight is dropped
+    generation: u64,
+    capacity: usize,
+    stop: bool,
+}
+
+impl ReadAhead {
+    fn spawn(
+        paths: Vec<PathBuf>,
+        auto_rotate: bool,
+        dimensions: (u32, u32),
+        capacity: usize,
+    ) -> Self {
+        let len = paths.len();
+        let shared = Arc::new((
+            Mutex::new(Window {
+                frames: VecDeque::with_capacity(capacity),
+                next: 0,
+                generation: 0,
+                capacity: capacity.max(1),
+                stop: false,
+            }),
+            Condvar::new(),
+        ));
+        let decoder = Arc::clone(&shared);
+        let handle = thread::spawn(move || {
+            let (window, changed) = &*decoder;
+            loop {
+                let (index, generation) = {
+                    let window = changed
+                        .wait_while(window.lock().unwrap(), |window| {
+                            !window.stop && window.frames.len() >= window.capacity
+                        })
+                        .unwrap();
+                    if window.stop {
+                        return;
+                    }
+                    (window.next, window.generation)
+                };
+                let frame = decode(&paths[index], auto_rotate);
+                let mut window = window.lock().unwrap();
+                if window.generation == generation {
+                    window.frames.push_back((index, frame));
+                    window.next = (index + 1) % len;
+                    changed.notify_all();
+                }
+            }
+        });
+        ReadAhead {
+            len,
+            dimensions,
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    fn frame(&self, index: usize) -> anyhow::Result<DynamicImage> {
+        let (window, changed) = &*self.shared;
+        let mut window = window.lock().unwrap();
+        // Frames before `index` were skipped, one that isn't coming up means a seek
+        if let Some(position) = window
+            .frames
+            .iter()
+            .position(|(queued, _)| *queued == index)
+        {
+            window.frames.drain(..position);
+        } else {
+            window.frames.clear();
+            if window.next != index {
+                window.next = index;
+                window.generation += 1;
+            }
+        }
+        changed.notify_all();
+        let mut window = changed
+            .wait_while(window, |window| window.frames.is_empty())
+            .unwrap();
+        let (_, frame) = window.frames.pop_front().unwrap();
+        changed.notify_all();
+        frame
+    }
+}
+
+impl Drop for ReadAhead {
+    fn drop(&mut self) {
+        let (window, changed) = &*self.shared;
+        window.lock().unwrap().stop = true;
+        changed.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Extensions of the files opened as videos, which `ffmpeg` decodes.
+pub const VIDEO_EXTENSIONS: [&str; 5] = ["mp4", "mkv", "webm", "mov", "avi"];
+
+/// A video decoded by an `ffmpeg` child process, which writes its frames to a pipe
+/// as a stream of PPM images at a fixed rate.
+pub struct Video {
+    path: PathBuf,
+    fps: u32,
+    /// Frames from `first` on are played, a clip cut from the middle starts late
+    first: usize,
+    len: usize,
+    dimensions: (u32, u32),
+    /// The running ffmpeg, its output and the index of the next frame it writes
+    stream: Option<(Child, BufReader<ChildStdout>, usize)>,
+    /// Frames of the whole video that failed to decode and why, given again when
+    /// they're asked for rather than restarting ffmpeg on them each time
+    failed: HashMap<usize, String>,
+}
+
+impl Video {
+    fn open(path: &Path, fps: u32) -> anyhow::Result<Self> {
+        let output = Command::new("ffprobe")
+            .args(["-v", "error", "-select_streams", "v:0"])
+            .args(["-show_entries", "stream=width,height:format=duration"])
+            .args(["-of", "default=noprint_wrappers=1"])
+            .arg(path)
+            .output()
+            .map_err(|error| ffmpeg_error(error, "ffprobe", path))?;
+        if !output.status.success() {
+            bail!(
+                "ffprobe failed on {}: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let mut width = None;
+        let mut height = None;
+        let mut duration = None;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            match line.split_once('=') {
+                Some(("width", value)) => width = value.parse::<u32>().ok(),
+                Some(("height", value)) => height = value.parse::<u32>().ok(),
+                Some(("duration", value)) => duration = value.parse::<f64>().ok(),
+                _ => {}
+            }
+        }
+        let (Some(width), Some(height), Some(duration)) = (width, height, duration) else {
+            return Err(NoFrames(format!("{} has no video stream", path.display())).into());
+        };
+        let len = (duration * fps as f64) as usize;
+        if len == 0 {
+            return Err(NoFrames(format!("{} has no frames", path.display())).into());
+        }
+        Ok(Video {
+            path: path.to_path_buf(),
+            fps,
+            first: 0,
+            len,
+            dimensions: (width, height),
+            stream: None,
+            failed: HashMap::new(),
+        })
+    }
+
+    /// Decodes frame `index`, restarting ffmpeg at that timestamp unless it is the
+    /// frame the running process writes next. After a frame fails the output can't
+    /// be trusted to line up, so ffmpeg is stopped and the next frame starts it anew.
+    fn frame(&mut self, index: usize) -> anyhow::Result<DynamicImage> {
+        if let Some(cause) = self.failed.get(&(self.first + index)) {
+            return Err(anyhow!("{}", cause).context(DecodeError(self.path.clone())));
+        }
+        if self
+            .stream
+            .as_ref()
+            .is_none_or(|(_, _, next)| *next != index)
+        {
+            self.stop();
+            let mut child = Command::new("ffmpeg")
+                .args(["-v", "error", "-nostdin"])
+                .arg("-ss")
+                .arg(format!(
+                    "{:.3}",
+                    (self.first + index) as f64 / self.fps as f64
+                ))
+                .arg("-i")
+                .arg(&self.path)
+                .arg("-vf")
+                .arg(format!("fps={}", self.fps))
+                .args(["-f", "image2pipe", "-vcodec", "ppm", "-"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .map_err(|error| ffmpeg_error(error, "ffmpeg", &self.path))?;
+            let stdout = BufReader::new(child.stdout.take().unwrap());
+            self.stream = Some((child, stdout, index));
+        }
+        let (_, stdout, next) = self.stream.as_mut().unwrap();
+        let frame = match read_ppm(stdout) {
+            Ok(Some(frame)) => frame,
+            result => {
+                let cause = match result {
+                    Err(err) => err.to_string(),
+                    _ => format!("{} ended before frame {}", self.path.display(), index),
+                };
+                self.stop();
+                self.failed.insert(self.first + index, cause.clone());
+                return Err(anyhow!("{}", cause).context(DecodeError(self.path.clone())));
+            }
+        };
+        *next += 1;
+        Ok(DynamicImage::ImageRgb8(frame))
+    }
+
+    fn stop(&mut self) {
+        if let Some((mut child, _, _)) = self.stream.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Drop for Video {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn ffmpeg_error(error: std::io::Error, program: &str, path: &Path) -> anyhow::Error {
+    if error.kind() == std::io::ErrorKind::NotFound {
+        anyhow!(
+            "{} is needed to play {} but was not found on PATH",
+            program,
+            path.display()
+        )
+    } else {
+        anyhow::Error::new(error).context(format!("failed to run {}", program))
+    }
+}
+
+/// Layout of the pixels in a raw stream.
+#[derive(Clone, Copy, Default, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RawFormat {
+    /// Three bytes per pixel, red, green and blue
+    #[default]
+    Rgb24,
+    /// Four bytes per pixel, red, green, blue and alpha
+    Rgba,
+}
+
+impl RawFormat {
+    /// Bytes each pixel takes.
+    pub fn channels(self) -> u32 {
+        match self {
+            RawFormat::Rgb24 => 3,
+            RawFormat::Rgba => 4,
+        }
+    }
+
+    /// Bytes a `width`x`height` frame takes, `None` past the 4 GiB a frame may.
+    pub fn frame_bytes(self, (width, height): (u32, u32)) -> Option<usize> {
+        frame_bytes((width, height), self.channels())
+    }
+}
+
+/// Most bytes a frame of raw pixels may take, 4 GiB, so a size mistyped by a digit
+/// or a corrupt header is turned down rather than allocated.
+const MAX_FRAME_BYTES: u64 = 1 << 32;
+
+fn frame_bytes((width, height): (u32, u32), channels: u32) -> Option<usize> {
+    let bytes = width as u64 * height as u64 * channels as u64;
+    (bytes <= MAX_FRAME_BYTES)
+        .then(|| usize::try_from(bytes).ok())
+        .flatten()
+}
+
+/// Size and pixel layout of raw frames, read from a JSON file written alongside
+/// them, such as `{"width": 384, "height": 216, "format": "rgb24"}`. The format is
+/// `rgb24` unless given.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RawInfo {
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub format: RawFormat,
+    /// Bytes each frame takes, which has to agree with the size and format when
+    /// it's given
+    pub frame_bytes: Option<u64>,
+}
+
+impl RawInfo {
+    pub fn load(path: &Path) -> anyhow::Result<RawInfo> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let info: RawInfo = serde_json::from_str(&text)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        if info.width == 0 || info.height == 0 {
+            bail!(
+                "{} declares {}x{} frames, which hold no pixels",
+                path.display(),
+                info.width,
+                info.height
+            );
+        }
+        let Some(size) = info.format.frame_bytes((info.width, info.height)) else {
+            bail!(
+                "{} declares {}x{} frames, which take more than {} bytes",
+                path.display(),
+                info.width,
+                info.height,
+                MAX_FRAME_BYTES
+            );
+        };
+        let size = size as u64;
+        if let Some(frame_bytes) = info.frame_bytes.filter(|&bytes| bytes != size) {
+            bail!(
+                "{} declares {} bytes per frame, but {}x{} frames of {} bytes per pixel take {}",
+                path.display(),
+                frame_bytes,
+                info.width,
+                info.height,
+                info.format.channels(),
+                size
+            );
+        }
+        Ok(info)
+    }
+}
+
+/// Frames of a fixed size written back to back as raw pixels, by something like
+/// `ffmpeg -f rawvideo` or a game rendering into a pipe. Frames can only be read in
+/// order, so the last one read is kept and shown again for any earlier index.
+pub struct Stream {
+    name: String,
+    reader: Box<dyn Read + Send>,
+    dimensions: (u32, u32),
+    format: RawFormat,
+    /// Bytes each frame takes
+    frame_bytes: usize,
+    /// The frame read last, `None` once the stream has ended
+    last: Option<DynamicImage>,
+    /// Index of the frame read next
+    next: usize,
+}
+
+impl Stream {
+    fn frame(&mut self, index: usize) -> anyhow::Result<Option<&DynamicImage>> {
+        // Frames skipped to keep up are read and dropped
+        while self.next <= index {
+            let (width, height) = self.dimensions;
+            // The dropped frame's pixels are read over
+            let mut data = match self.last.take() {
+                Some(frame) => frame.into_bytes(),
+                None => vec![0; self.frame_bytes],
+            };
+            let filled = read_full(&mut self.reader, &mut data)
+                .with_context(|| format!("failed to read {}", self.name))?;
+            if filled < data.len() {
+                if filled > 0 {
+                    eprintln!("{} ended partway through a frame, dropping it", self.name);
+                }
+                return Ok(None);
+            }
+            self.last = Some(match self.format {
+                RawFormat::Rgb24 => {
+                    DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, data).unwrap())
+                }
+                RawFormat::Rgba => {
+                    DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, data).unwrap())
+                }
+            });
+            self.next += 1;
+        }
+        Ok(self.last.as_ref())
+    }
+}
+
+/// Reads until `buf` is full or the reader ends, pipes hand over a frame in pieces.
+/// Returns how much was read.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+/// Opens `path` as a stream of raw `width`x`height` frames in `format`, `-` for
+/// stdin. Named pipes work like any other file.
+pub fn open_raw(
+    path: &Path,
+    (width, height): (u32, u32),
+    format: RawFormat,
+) -> anyhow::Result<Source> {
+    let Some(frame_bytes) = format.frame_bytes((width, height)) else {
+        bail!(
+            "raw frames of {}x{} pixels take more than {} bytes",
+            width,
+            height,
+            MAX_FRAME_BYTES
+        );
+    };
+    let (name, reader): (String, Box<dyn Read + Send>) = if path == Path::new("-") {
+        ("stdin".into(), Box::new(std::io::stdin()))
+    } else {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        (path.display().to_string(), Box::new(file))
+    };
+    Ok(Source::Stream(Stream {
+        name,
+        reader,
+        dimensions: (width, height),
+        format,
+        frame_bytes,
+        last: None,
+        next: 0,
+    }))
+}
+
+/// Reads one binary PPM image, `None` at the end of the stream.
+fn read_ppm(reader: &mut impl BufRead) -> std::io::Result<Option<RgbImage>> {
+    // Header is "P6", width, height and the maximum value separated by whitespace,
+    // followed by a single whitespace byte before the pixels
+    let mut fields = Vec::with_capacity(4);
+    let mut field = String::new();
+    for byte in reader.by_ref().bytes() {
+        let byte = byte?;
+        if !byte.is_ascii_whitespace() {
+            field.push(byte as char);
+        } else if !field.is_empty() {
+            fields.push(std::mem::take(&mut field));
+            if fields.len() == 4 {
+                break;
+            }
+        }
+    }
+    if fields.is_empty() && field.is_empty() {
+        return Ok(None);
+    }
+    let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed PPM header");
+    let [magic, width, height, max] = fields.as_slice() else {
+        return Err(invalid());
+    };
+    let width = width.parse::<u32>().map_err(|_| invalid())?;
+    let height = height.parse::<u32>().map_err(|_| invalid())?;
+    if magic != "P6" || max != "255" {
+        return Err(invalid());
+    }
+    let bytes = frame_bytes((width, height), 3).ok_or_else(invalid)?;
+    let mut data = vec![0; bytes];
+    reader.read_exact(&mut data)?;
+    Ok(RgbImage::from_raw(width, height, data))
+}
+
+/// The error of a file that couldn't be decoded, with why as its cause. Kept apart
+/// from other errors so the player can exit with a code of its own for it.
+#[derive(Debug)]
+pub struct DecodeError(pub PathBuf);
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to decode {}", self.0.display())
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// The error of a source without a single frame to play, saying where none were
+/// found. Kept apart from other errors like `DecodeError`.
+#[derive(Debug)]
+pub struct NoFrames(pub String);
+
+impl fmt::Display for NoFrames {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for NoFrames {}
+
+/// Opens `path` as a source along with how many times it plays, `None` meaning
+/// forever. An animated GIF or WebP plays as often as its own loop count says. Videos are
+/// sampled at `fps`. A directory is scanned for images in `formats`, or in any format
+/// `image` decodes when `formats` is empty. A `path` that doesn't exist is a plain
+/// error, like a file missing from a playlist.
+pub fn open_source(
+    path: &Path,
+    fps: u32,
+    formats: &[ImageFormat],
+) -> anyhow::Result<(Source, Option<u32>)> {
+    // A mistyped path isn't a broken frame
+    if !path.exists() {
+        bail!("{} doesn't exist", path.display());
+    }
+    let has_extension = |extensions: &[&str]| {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                extensions
+                    .iter()
+                    .any(|candidate| extension.eq_ignore_ascii_case(candidate))
+            })
+    };
+    if path.is_file() && has_extension(&VIDEO_EXTENSIONS) {
+        return Ok((Source::Video(Video::open(path, fps)?), None));
+    }
+    let context = || DecodeError(path.to_path_buf());
+    let open = || File::open(path).map(BufReader::new).with_context(context);
+    let animation = if path.is_file() && has_extension(&["gif"]) {
+        let frames = GifDecoder::new(open()?)
+            .with_context(context)?
+            .into_frames();
+        Some((frames, gif_loops(path).with_context(context)?))
+    } else if path.is_file() && has_extension(&["webp"]) {
+        let decoder = WebPDecoder::new(open()?).with_context(context)?;
+        // A still WebP is shown like any other image
+        if decoder.has_animation() {
+            Some((
+                decoder.into_frames(),
+                webp_loops(path).with_context(context)?,
+            ))
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+    let Some((frames, loops)) = animation else {
+        let paths = get_path(path, formats)?;
+        if paths.is_empty() {
+            return Err(NoFrames(format!("no images found in {}", path.display())).into());
+        }
+        return Ok(files(paths));
+    };
+    let frames = frames
+        .map(|frame| {
+            let frame = frame.with_context(context)?;
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            // A zero delay means "as fast as possible", leave those to --fps
+            let delay =
+                (numer > 0).then(|| Duration::from_secs_f64(numer as f64 / denom as f64 / 1000.0));
+            Ok((DynamicImage::ImageRgba8(frame.into_buffer()), delay))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if frames.is_empty() {
+        return Err(NoFrames(format!("{} has no frames", path.display())).into());
+    }
+    Ok((Source::Animation(frames), loops))
+}
+
+/// Opens the frames listed in the playlist at `path`, one image path per line and
+/// played in that order. Blank lines and lines starting with `#` are passed over, and
+/// relative paths are taken from the playlist's directory. A listed file that doesn't
+/// exist is an error when `strict`, otherwise it's left out with a warning.
+pub fn open_playlist(path: &Path, strict: bool) -> anyhow::Result<(Source, Option<u32>)> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut paths = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let frame = base.join(line);
+        if frame.is_file() {
+            paths.push(frame);
+            continue;
+        }
+        let problem = format!("{}:{}: {} doesn't exist", path.display(), number + 1, line);
+        if strict {
+            bail!(problem);
+        }
+        eprintln!("warning: {}, leaving it out", problem);
+    }
+    if paths.is_empty() {
+        return Err(NoFrames(format!("{} lists no frames", path.display())).into());
+    }
+    Ok(files(paths))
+}
+
+/// The image files at `paths` as a source, along with how many times it plays.
+fn files(paths: Vec<PathBuf>) -> (Source, Option<u32>) {
+    // A single image is rendered once instead of looping forever
+    let loops = if paths.len() > 1 { None } else { Some(1) };
+    (
+        Source::Files {
+            paths,
+            auto_rotate: true,
+        },
+        loops,
+    )
+}
+
+/// Times a GIF plays, from its NETSCAPE extension, which `image` doesn't expose.
+/// The extension counts the repeats after the first play, zero meaning forever, and a
+/// GIF without one plays once. It may follow the first frame, so the whole file is
+/// walked.
+fn gif_loops(path: &Path) -> anyhow::Result<Option<u32>> {
+    let file = BufReader::new(File::open(path)?);
+    let mut options = gif::DecodeOptions::new();
+    options.skip_frame_decoding(true);
+    let mut decoder = options.read_info(file)?;
+    while decoder.next_frame_info()?.is_some() {}
+    Ok(match decoder.repeat() {
+        gif::Repeat::Infinite => None,
+        gif::Repeat::Finite(repeats) => Some(repeats as u32 + 1),
+    })
+}
+
+/// Loop count of an animated WebP from its ANIM chunk, which `image` doesn't expose
+/// either. Zero there means forever.
+fn webp_loops(path: &Path) -> anyhow::Result<Option<u32>> {
+    let decoder = image_webp::WebPDecoder::new(BufReader::new(File::open(path)?))?;
+    Ok(match decoder.loop_count() {
+        image_webp::LoopCount::Forever => None,
+        image_webp::LoopCount::Times(loops) => Some(loops.get() as u32),
+    })
+}
+
+fn get_path(path: &Path, formats: &[ImageFormat]) -> anyhow::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut paths = Vec::new();
+    let entries =
+        std::fs::read_dir(path).with_context(|| format!("failed to read {}", path.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        // Mixed folders are fine, anything that isn't a frame is passed over
+        match ImageFormat::from_path(&path) {
+            Ok(format) if !format.reading_enabled() => {
+                eprintln!("skipping {}, {:?} can't be decoded", path.display(), format)
+            }
+            Ok(format) if !formats.is_empty() && !formats.contains(&format) => {}
+            Ok(_) => paths.push(path),
+            Err(_) => eprintln!("skipping {}, not an image", path.display()),
+        }
+    }
+    // Frames are numbered, so `2.jpeg` has to come before `10.jpeg`
+    paths.sort_by(|a, b| {
+        natural_cmp(
+            &a.file_name().unwrap_or_default().to_string_lossy(),
+            &b.file_name().unwrap_or_default().to_string_lossy(),
+        )
+    });
+    Ok(paths)
+}
+
+/// Compares names the way people read them: runs of digits by their value, the rest
+/// character by character. Of two equal numbers the one with fewer leading zeros
+/// comes first, so the order stays total.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(first_a), Some(first_b)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        if !(first_a.is_ascii_digit() && first_b.is_ascii_digit()) {
+            match first_a.cmp(&first_b) {
+                Ordering::Equal => {
+                    a = &a[first_a.len_utf8()..];
+                    b = &b[first_b.len_utf8()..];
+                    continue;
+                }
+                unequal => return unequal,
+            }
+        }
+        let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number_a, rest_a) = a.split_at(digits(a));
+        let (number_b, rest_b) = b.split_at(digits(b));
+        let (value_a, value_b) = (
+            number_a.trim_start_matches('0'),
+            number_b.trim_start_matches('0'),
+        );
+        let order = value_a
+            .len()
+            .cmp(&value_b.len())
+            .then_with(|| value_a.cmp(value_b))
+            .then_with(|| number_a.len().cmp(&number_b.len()));
+        if order != Ordering::Equal {
+            return order;
+        }
+        (a, b) = (rest_a, rest_b);
+    }
+}
+
+/// Decodes the image at `path`, turned upright by its EXIF orientation when
+/// `auto_rotate`. Formats without EXIF data are left as they are.
+fn decode(path: &Path, auto_rotate: bool) -> anyhow::Result<DynamicImage> {
+    let context = || DecodeError(path.to_path_buf());
+    let mut decoder = ImageReader::open(path)
+        .with_context(context)?
+        .into_decoder()
+        .with_context(context)?;
+    let orientation = match auto_rotate {
+        true => decoder.orientation().with_context(context)?,
+        false => Orientation::NoTransforms,
+    };
+    let mut image = DynamicImage::from_decoder(decoder).with_context(context)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// Size of the image at `path` as `decode` turns it, read from its header.
+fn image_dimensions(path: &Path, auto_rotate: bool) -> image::ImageResult<(u32, u32)> {
+    let mut decoder = ImageReader::open(path)?.into_decoder()?;
+    let (width, height) = decoder.dimensions();
+    let turned = auto_rotate
+        && matches!(
+            decoder.orientation()?,
+            Orientation::Rotate90
+                | Orientation::Rotate270
+                | Orientation::Rotate90FlipH
+                | Orientation::Rotate270FlipH
+        );
+    Ok(if turned {
+        (height, width)
+    } else {
+        (width, height)
+    })
+}
+
+fn preload_images(
+    paths: &[PathBuf],
+    auto_rotate: bool,
+    strict: bool,
+) -> anyhow::Result<Vec<image::DynamicImage>> {
+    let frames = paths
+        .par_iter()
+        .map(|path| decode(path, auto_rotate))
+        .collect::<Vec<_>>();
+    if strict {
+        return frames.into_iter().collect();
+    }
+    let frames = frames
+        .into_iter()
+        .filter_map(|frame| {
+            frame
+                .inspect_err(|err| {
+                    eprintln!("warning: {}: {}, leaving it out", err, err.root_cause())
+                })
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    if frames.is_empty() {
+        return Err(NoFrames(format!(
+            "none of the {} frames could be decoded",
+            paths.len()
+        ))
+        .into());
+    }
+    Ok(frames)
+}
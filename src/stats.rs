@@ -0,0 +1,99 @@
+//! How long each frame spends in each stage of the pipeline, and how far ahead of
+//! the screen conversion runs, summed up at exit with `--stats`.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A stage every frame goes through.
+#[derive(Clone, Copy)]
+pub enum Stage {
+    /// Reading the frame from its source
+    Decode,
+    /// Converting the frame into cells and escapes
+    Convert,
+    /// Writing the frame to the terminal or the output
+    Write,
+}
+
+const STAGES: [(Stage, &str); 3] = [
+    (Stage::Decode, "decode"),
+    (Stage::Convert, "convert"),
+    (Stage::Write, "write"),
+];
+
+/// Timings of the stages, recorded from any thread. Without `--stats` nothing is
+/// recorded and timing a stage just runs it.
+pub struct Stats {
+    timings: Option<Mutex<[Vec<Duration>; 3]>>,
+    /// Frames converted and waiting, counting the one about to be shown, each time
+    /// the player took one
+    ahead: Option<Mutex<Vec<usize>>>,
+}
+
+impl Stats {
+    pub fn new(enabled: bool) -> Self {
+        Stats {
+            timings: enabled.then(|| Mutex::new(Default::default())),
+            ahead: enabled.then(|| Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Records that `frames` were ready when the player took the next one.
+    pub fn ahead(&self, frames: usize) {
+        if let Some(ahead) = &self.ahead {
+            ahead.lock().unwrap().push(frames);
+        }
+    }
+
+    /// Runs `f` as one frame's `stage`.
+    pub fn time<T>(&self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let Some(timings) = &self.timings else {
+            return f();
+        };
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+        timings.lock().unwrap()[stage as usize].push(elapsed);
+        result
+    }
+
+    /// Prints the shortest, mean, 95th percentile and longest time of each stage to
+    /// stderr, where it stays out of the frames written to stdout.
+    pub fn print(&self) {
+        let Some(timings) = &self.timings else {
+            return;
+        };
+        let timings = timings.lock().unwrap();
+        eprintln!(
+            "{:<8} {:>7} {:>9} {:>9} {:>9} {:>9}",
+            "stage", "frames", "min ms", "mean ms", "p95 ms", "max ms"
+        );
+        for (stage, name) in STAGES {
+            let mut times = timings[stage as usize].clone();
+            if times.is_empty() {
+                continue;
+            }
+            times.sort_unstable();
+            let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+            let mean = times.iter().sum::<Duration>() / times.len() as u32;
+            // The time that 95% of the frames took at most
+            let p95 = times[(times.len() * 95).div_ceil(100) - 1];
+            eprintln!(
+                "{:<8} {:>7} {:>9.2} {:>9.2} {:>9.2} {:>9.2}",
+                name,
+                times.len(),
+                ms(times[0]),
+                ms(mean),
+                ms(p95),
+                ms(times[times.len() - 1])
+            );
+        }
+        let ahead = self.ahead.as_ref().unwrap().lock().unwrap();
+        if let Some(&most) = ahead.iter().max() {
+            let mean = ahead.iter().sum::<usize>() as f64 / ahead.len() as f64;
+            eprintln!("frames ready ahead: {mean:.1} on average, {most} at most");
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! `--invert`, `--brightness` and `--contrast` on a cell of a known color.
+
+use ascii_art::{render_frame, ColorMode, FrameData, RenderOptions, RenderOptionsBuilder};
+use image::{DynamicImage, Rgb, RgbImage};
+
+fn builder() -> RenderOptionsBuilder {
+    RenderOptions::builder()
+        .color_mode(ColorMode::Truecolor)
+        .pixel_width(1)
+}
+
+/// Glyph and color a cell of `color` is drawn with.
+fn cell(color: [u8; 3], builder: RenderOptionsBuilder) -> (char, [u8; 3]) {
+    let options = builder.build().unwrap();
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, Rgb(color)));
+    let mut frame = FrameData::new(1, 1);
+    render_frame(&img, &mut frame, None, &options);
+    let data = String::from_utf8(frame.data).unwrap();
+    let (_, rest) = data
+        .split_once("\x1b[38;2;")
+        .unwrap_or_else(|| panic!("no color in {data:?}"));
+    let (rgb, rest) = rest.split_once('m').unwrap();
+    let mut channels = rgb.split(';').map(|channel| channel.parse().unwrap());
+    let color = [(); 3].map(|_| channels.next().unwrap());
+    (rest.chars().next().unwrap(), color)
+}
+
+#[test]
+fn invert() {
+    // The glyph comes from the other end of the ramp, the color stays
+    assert_eq!(cell([0; 3], builder()), (' ', [0; 3]));
+    assert_eq!(cell([0; 3], builder().invert(true)), ('@', [0; 3]));
+    assert_eq!(cell([255; 3], builder().invert(true)), (' ', [255; 3]));
+}
+
+#[test]
+fn brightness() {
+    // A quarter of full brightness added to each channel, clamped at white
+    let (_, color) = cell([100, 20, 240], builder().brightness(0.25));
+    assert_eq!(color, [164, 84, 255]);
+    let (_, color) = cell([100, 20, 240], builder().brightness(-0.25));
+    assert_eq!(color, [36, 0, 176]);
+}
+
+#[test]
+fn contrast() {
+    // Channels pushed away from the middle, or drawn towards it
+    let (_, color) = cell([100, 20, 160], builder().contrast(2.0));
+    assert_eq!(color, [73, 0, 193]);
+    let (_, color) = cell([100, 20, 160], builder().contrast(0.5));
+    assert_eq!(color, [114, 74, 144]);
+}
+
+#[test]
+fn contrast_before_brightness() {
+    let (_, color) = cell([100; 3], builder().contrast(2.0).brightness(0.1));
+    assert_eq!(color, [98; 3]);
+}
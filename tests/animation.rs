@@ -0,0 +1,151 @@
+//! Animated WebPs play frame by frame, each for as long as the file says, and loop as
+//! often as it says, the same as GIFs do. A still WebP is an image like any other.
+
+use std::{path::PathBuf, time::Duration};
+
+use ascii_art::{open_source, Source};
+use image::{
+    codecs::{
+        gif::{GifEncoder, Repeat},
+        webp::WebPEncoder,
+    },
+    Delay, Frame, GenericImageView, Rgba, RgbaImage,
+};
+
+/// A chunk of a RIFF file, padded to an even length.
+fn chunk(name: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = name.to_vec();
+    chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+fn u24(value: u32) -> [u8; 3] {
+    let [a, b, c, _] = value.to_le_bytes();
+    [a, b, c]
+}
+
+/// Writes an animated WebP of 8x4 frames filled with `colors`, shown for as many
+/// milliseconds as given with each, and played `loops` times, zero for forever.
+/// `image` only encodes still WebPs, so each frame is encoded on its own and its
+/// bitstream put in an animation frame.
+fn animated_webp(name: &str, frames: &[([u8; 4], u32)], loops: u16) -> PathBuf {
+    let (width, height) = (8, 4);
+    let mut vp8x = vec![0x10 | 0x02, 0, 0, 0];
+    vp8x.extend_from_slice(&u24(width - 1));
+    vp8x.extend_from_slice(&u24(height - 1));
+    let mut anim = vec![0; 4];
+    anim.extend_from_slice(&loops.to_le_bytes());
+    let mut body = b"WEBP".to_vec();
+    body.extend(chunk(b"VP8X", &vp8x));
+    body.extend(chunk(b"ANIM", &anim));
+    for &(color, duration) in frames {
+        let mut still = Vec::new();
+        RgbaImage::from_pixel(width, height, Rgba(color))
+            .write_with_encoder(WebPEncoder::new_lossless(&mut still))
+            .unwrap();
+        // RIFF header, then the one VP8L chunk
+        let bitstream = &still[12..];
+        let mut anmf = [
+            u24(0),
+            u24(0),
+            u24(width - 1),
+            u24(height - 1),
+            u24(duration),
+        ]
+        .concat();
+        // Drawn over the canvas without blending, left there
+        anmf.push(0x02);
+        anmf.extend_from_slice(bitstream);
+        body.extend(chunk(b"ANMF", &anmf));
+    }
+    let path = std::env::temp_dir().join(format!("ascii_art-{}-{}.webp", std::process::id(), name));
+    std::fs::write(&path, chunk(b"RIFF", &body)).unwrap();
+    path
+}
+
+#[test]
+fn frames_and_delays() {
+    let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+    let path = animated_webp(
+        "delays",
+        &[(colors[0], 100), (colors[1], 0), (colors[2], 250)],
+        0,
+    );
+    let (source, loops) = open_source(&path, 10, &[]).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let Source::Animation(frames) = source else {
+        panic!("not decoded as an animation");
+    };
+    assert_eq!(loops, None);
+    let delays = frames.iter().map(|(_, delay)| *delay).collect::<Vec<_>>();
+    // A zero delay is left to --fps, as in a GIF
+    assert_eq!(
+        delays,
+        [
+            Some(Duration::from_millis(100)),
+            None,
+            Some(Duration::from_millis(250))
+        ]
+    );
+    for ((frame, _), color) in frames.iter().zip(colors) {
+        assert_eq!(frame.dimensions(), (8, 4));
+        assert_eq!(frame.get_pixel(3, 2), Rgba(color));
+    }
+}
+
+#[test]
+fn loop_count() {
+    let path = animated_webp("loops", &[([0; 4], 50), ([255; 4], 50)], 3);
+    let (source, loops) = open_source(&path, 10, &[]).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(source.len(), 2);
+    assert_eq!(loops, Some(3));
+}
+
+/// Writes a GIF of two 8x4 frames, repeated as often as `repeat` says after it's
+/// first played.
+fn animated_gif(name: &str, repeat: Repeat) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("ascii_art-{}-{}.gif", std::process::id(), name));
+    let mut encoder = GifEncoder::new(std::fs::File::create(&path).unwrap());
+    encoder.set_repeat(repeat).unwrap();
+    for color in [[0, 0, 0, 255], [255; 4]] {
+        let delay = Delay::from_numer_denom_ms(50, 1);
+        let frame = Frame::from_parts(RgbaImage::from_pixel(8, 4, Rgba(color)), 0, 0, delay);
+        encoder.encode_frame(frame).unwrap();
+    }
+    drop(encoder);
+    path
+}
+
+#[test]
+fn gif_loop_count() {
+    // Without a NETSCAPE extension it plays once, with one it plays once more than the
+    // repeats it counts, forever for a count of zero
+    for (name, repeat, expected) in [
+        ("once", Repeat::Finite(0), Some(1)),
+        ("repeated", Repeat::Finite(2), Some(3)),
+        ("forever", Repeat::Infinite, None),
+    ] {
+        let path = animated_gif(name, repeat);
+        let (source, loops) = open_source(&path, 10, &[]).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(source.len(), 2);
+        assert_eq!(loops, expected, "{name}");
+    }
+}
+
+#[test]
+fn still_webp() {
+    let path = std::env::temp_dir().join(format!("ascii_art-{}-still.webp", std::process::id()));
+    RgbaImage::from_pixel(8, 4, Rgba([10, 20, 30, 255]))
+        .save(&path)
+        .unwrap();
+    let (source, loops) = open_source(&path, 10, &[]).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(source, Source::Files { .. }));
+    assert_eq!(loops, Some(1));
+}
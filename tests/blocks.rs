@@ -0,0 +1,69 @@
+//! Frames converted in blocks of rows that don't divide them evenly, the last block
+//! taking the rows left over.
+
+use ascii_art::{render_frame, ColorMode, FrameData, RenderOptions};
+use image::{DynamicImage, Rgb, RgbImage};
+
+/// Escapes drawing a white frame of `rows` rows, 3 cells of a column each wide,
+/// converted in blocks of `block_rows` rows.
+fn render(rows: u32, block_rows: u32) -> (FrameData, String) {
+    let options = RenderOptions::builder()
+        .color_mode(ColorMode::Mono)
+        .pixel_width(1)
+        .block_rows(block_rows)
+        .build()
+        .unwrap();
+    let (cell_width, cell_height) = options.cell_pixels();
+    let white = RgbImage::from_pixel(3 * cell_width, rows * cell_height, Rgb([255; 3]));
+    let mut frame = FrameData::new(3, rows);
+    render_frame(&DynamicImage::ImageRgb8(white), &mut frame, None, &options);
+    let data = String::from_utf8(frame.data.clone()).unwrap();
+    (frame, data)
+}
+
+/// Number of glyphs `data` draws, leaving out its escapes.
+fn glyphs(data: &str) -> usize {
+    let mut count = 0;
+    let mut chars = data.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                chars.find(char::is_ascii_alphabetic);
+            }
+            c if !c.is_whitespace() => count += 1,
+            _ => {}
+        }
+    }
+    count
+}
+
+#[test]
+fn every_row_drawn() {
+    for (rows, block_rows) in [(13, 5), (7, 2), (25, 24), (10, 3)] {
+        let (frame, data) = render(rows, block_rows);
+        // Each row starts with a move there, to its position at the start of a block
+        // and to the next line within one
+        let positions = (1..=rows)
+            .filter(|row| data.contains(&format!("\x1b[{row};1H")))
+            .count();
+        let next_lines = data.matches("\x1b[E").count();
+        assert_eq!(
+            positions + next_lines,
+            rows as usize,
+            "{rows} rows in blocks of {block_rows}: {data:?}"
+        );
+        assert_eq!(glyphs(&data), 3 * rows as usize, "{data:?}");
+        assert_eq!(frame.cells.len(), 3 * rows as usize);
+    }
+}
+
+#[test]
+fn last_row_of_217() {
+    // Nine blocks of 24 rows, and row 216 in a block of its own
+    let (frame, data) = render(217, 24);
+    let (_, last) = data
+        .split_once("\x1b[217;1H")
+        .unwrap_or_else(|| panic!("row 216 missing from {data:?}"));
+    assert_eq!(glyphs(last), 3, "{last:?}");
+    assert_eq!(frame.cells.len(), 3 * 217);
+}
@@ -0,0 +1,77 @@
+//! The checks `RenderOptionsBuilder::build` makes on the options.
+
+use ascii_art::{ColorMode, Filter, Ramp, RenderOptions, RenderOptionsBuilder};
+
+#[test]
+fn defaults() {
+    let options = RenderOptions::builder()
+        .color_mode(ColorMode::Ansi256)
+        .build()
+        .unwrap();
+    assert!(matches!(options.color, ColorMode::Ansi256));
+    assert!(matches!(options.ramp, Ramp::Short));
+    assert!(matches!(options.filter, Filter::Nearest));
+    assert_eq!(options.pixel_width, 2);
+    assert_eq!(options.cell_aspect, 2.0);
+    assert_eq!((options.contrast, options.brightness), (1.0, 0.0));
+    assert!(!options.braille && !options.half_block && !options.dither);
+}
+
+#[test]
+fn setters() {
+    let options = RenderOptions::builder()
+        .ramp(Ramp::Long)
+        .filter(Filter::Lanczos3)
+        .half_block(true)
+        .contrast(1.5)
+        .background([1, 2, 3])
+        .block_rows(4)
+        .seed(42)
+        .build()
+        .unwrap();
+    assert!(matches!(options.ramp, Ramp::Long));
+    assert!(matches!(options.filter, Filter::Lanczos3));
+    assert!(options.half_block);
+    assert_eq!(options.contrast, 1.5);
+    assert_eq!(options.background, Some([1, 2, 3]));
+    assert_eq!(options.block_rows, Some(4));
+    assert_eq!(options.seed, 42);
+}
+
+#[test]
+fn conflicting_styles() {
+    let builder = RenderOptions::builder();
+    assert!(builder.braille(true).half_block(true).build().is_err());
+    assert!(builder.edges(true).braille(true).build().is_err());
+    assert!(builder.braille(true).dither(true).build().is_err());
+    assert!(builder.edges(true).edge_threshold(0.5).build().is_err());
+    assert!(builder.half_block(true).pixel_width(1).build().is_err());
+    assert!(builder.dither(true).pixel_width(1).build().is_ok());
+    // The pixel width follows the cell aspect unless it's given, as on the command
+    // line, but braille and half block cells stay a column wide
+    let pixel_width = |builder: RenderOptionsBuilder| builder.build().unwrap().pixel_width;
+    assert_eq!(pixel_width(builder.cell_aspect(1.0)), 1);
+    assert_eq!(pixel_width(builder.cell_aspect(2.6)), 3);
+    assert_eq!(pixel_width(builder.cell_aspect(2.6).pixel_width(2)), 2);
+    assert!(builder.braille(true).cell_aspect(3.0).build().is_ok());
+}
+
+#[test]
+fn out_of_range() {
+    let builder = RenderOptions::builder();
+    assert!(builder.pixel_width(0).build().is_err());
+    assert!(builder.pixel_width(9).build().is_err());
+    assert!(builder.cell_aspect(0.4).build().is_err());
+    assert!(builder.cell_aspect(f32::NAN).build().is_err());
+    assert!(builder.contrast(-1.0).build().is_err());
+    assert!(builder.contrast(f32::NAN).build().is_err());
+    assert!(builder.brightness(1.5).build().is_err());
+    assert!(builder.block_rows(0).build().is_err());
+    assert!(builder.color_levels(1).build().is_err());
+    assert!(builder.posterize(0).build().is_err());
+    assert!(builder.posterize(9).build().is_err());
+    assert!(builder.crop((0, 0, 0, 4)).build().is_err());
+    assert!(builder.edge_threshold(0.0).build().is_err());
+    assert!(builder.edge_threshold(1.5).build().is_err());
+    assert!(builder.ramp(Ramp::Custom(&[])).build().is_err());
+}
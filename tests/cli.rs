@@ -0,0 +1,481 @@
+//! Runs the player binary the way a script would, with stdout going down a pipe.
+
+use std::{
+    io::Read,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use image::{Rgb, RgbImage};
+
+/// A small colorful image in a file of its own, for the binary to open.
+fn image_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("ascii_art-{}-{}.png", std::process::id(), name));
+    RgbImage::from_fn(16, 8, |x, y| Rgb([x as u8 * 16, y as u8 * 32, 128]))
+        .save(&path)
+        .unwrap();
+    path
+}
+
+fn run(args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_ascii_art"))
+        .args(args)
+        // Would pick 24-bit colors on a terminal
+        .env("COLORTERM", "truecolor")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output.stdout
+}
+
+#[test]
+fn piped_output_is_plain() {
+    let path = image_file("plain");
+    let stdout = run(&[path.to_str().unwrap(), "--scale", "2"]);
+    std::fs::remove_file(&path).unwrap();
+    assert!(
+        !stdout.contains(&0x1b),
+        "{}",
+        String::from_utf8_lossy(&stdout)
+    );
+    let text = String::from_utf8(stdout).unwrap();
+    assert_eq!(text.lines().count(), 4);
+    assert!(text.lines().all(|line| line.chars().count() == 16));
+}
+
+#[test]
+fn piped_output_keeps_asked_for_colors() {
+    let path = image_file("color");
+    let stdout = run(&[
+        path.to_str().unwrap(),
+        "--scale",
+        "2",
+        "--color",
+        "truecolor",
+    ]);
+    std::fs::remove_file(&path).unwrap();
+    assert!(stdout.windows(2).any(|window| window == b"\x1b["));
+}
+
+#[test]
+fn piped_output_has_no_title() {
+    let path = image_file("title");
+    let stdout = run(&[
+        path.to_str().unwrap(),
+        "--scale",
+        "2",
+        "--color",
+        "truecolor",
+        "--title",
+    ]);
+    std::fs::remove_file(&path).unwrap();
+    assert!(!stdout.windows(2).any(|window| window == b"\x1b]"));
+}
+
+#[test]
+fn once() {
+    let dir = std::env::temp_dir().join(format!("ascii_art-{}-once", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (i, gray) in [0, 128, 255].into_iter().enumerate() {
+        RgbImage::from_fn(16, 8, |x, _| Rgb([gray, x as u8 * 16, 64]))
+            .save(dir.join(format!("{i}.png")))
+            .unwrap();
+    }
+    let args = ["--scale", "2", "--color", "truecolor"];
+    let picked = run(&[
+        &[dir.to_str().unwrap(), "--once", "--start", "2"],
+        &args[..],
+    ]
+    .concat());
+    let second = run(&[&[dir.join("1.png").to_str().unwrap()], &args[..]].concat());
+    let all = run(&[&[dir.to_str().unwrap()], &args[..]].concat());
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(picked, second);
+    assert!(all.len() > picked.len());
+}
+
+#[test]
+fn list_formats() {
+    let stdout = String::from_utf8(run(&["--list-formats"])).unwrap();
+    let row = |label: &str| {
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix(label))
+            .unwrap_or_else(|| panic!("{stdout}"))
+            .trim()
+            .to_owned()
+    };
+    assert_eq!(row("$COLORTERM"), "truecolor");
+    assert_eq!(row("advertised"), "truecolor");
+    assert!(row("stdout").starts_with("redirected"));
+    assert!(row("images").split(", ").any(|format| format == "png"));
+    assert!(row("videos").starts_with("mp4"));
+}
+
+/// What the binary writes into a file with `NO_COLOR` set, where it would pick
+/// 24-bit colors otherwise.
+fn no_color_output(name: &str, args: &[&str]) -> Vec<u8> {
+    let path = image_file(name);
+    let output = path.with_extension("txt");
+    let status = Command::new(env!("CARGO_BIN_EXE_ascii_art"))
+        .args([path.to_str().unwrap(), "--scale", "2", "-o"])
+        .arg(&output)
+        .args(args)
+        .env("COLORTERM", "truecolor")
+        .env("NO_COLOR", "")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let written = std::fs::read(&output).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&output).unwrap();
+    written
+}
+
+#[test]
+fn no_color() {
+    let written = no_color_output("no-color", &[]);
+    assert!(!written.windows(4).any(|window| window == b"38;2"));
+    assert!(!written.contains(&0x1b));
+}
+
+#[test]
+fn color_flag_overrides_no_color() {
+    let written = no_color_output("no-color-flag", &["--color", "truecolor"]);
+    assert!(written.windows(4).any(|window| window == b"38;2"));
+}
+
+#[test]
+fn closed_pipe_ends_cleanly() {
+    let path = image_file("closed");
+    // Far more than a pipe buffers, so writing blocks until the read end is closed
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ascii_art"))
+        .args([path.to_str().unwrap(), "--scale", "1", "--loop", "10000"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let mut start = [0; 64];
+    stdout.read_exact(&mut start).unwrap();
+    drop(stdout);
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(
+        output.status.success() && output.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn exit_codes() {
+    let dir = std::env::temp_dir().join(format!("ascii_art-{}-exit", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let code = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_ascii_art"))
+            .arg(&dir)
+            .args(args)
+            .output()
+            .unwrap()
+            .status
+            .code()
+    };
+    let empty = code(&[]);
+    let missing = Command::new(env!("CARGO_BIN_EXE_ascii_art"))
+        .arg(dir.join("missing.png"))
+        .output()
+        .unwrap();
+    let image = image_file("exit");
+    std::fs::copy(&image, dir.join("1.png")).unwrap();
+    std::fs::write(dir.join("2.png"), b"not a png").unwrap();
+    let (lenient, strict) = (code(&["--loop", "1"]), code(&["--loop", "1", "--strict"]));
+    std::fs::remove_dir_all(&dir).unwrap();
+    std::fs::remove_file(&image).unwrap();
+    assert_eq!(empty, Some(4));
+    // A path that isn't there is no broken frame
+    assert_eq!(missing.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&missing.stderr);
+    assert!(stderr.contains("doesn't exist"), "{stderr}");
+    // The broken frame is left out, unless --strict has it end playback
+    assert_eq!(lenient, Some(0));
+    assert_eq!(strict, Some(3));
+}
+
+#[test]
+fn dump_dir() {
+    let dir = std::env::temp_dir().join(format!("ascii_art-{}-dump", std::process::id()));
+    let frames = dir.join("frames");
+    std::fs::create_dir_all(&frames).unwrap();
+    for i in 0..3u8 {
+        RgbImage::from_pixel(16, 8, Rgb([i * 100, 50, 50]))
+            .save(frames.join(format!("{i}.png")))
+            .unwrap();
+    }
+    // Made along with the directory it's in
+    let dump = dir.join("out").join("frames");
+    run(&[
+        frames.to_str().unwrap(),
+        "--loop",
+        "4",
+        "--color",
+        "truecolor",
+        "--dump-dir",
+        dump.to_str().unwrap(),
+    ]);
+    let mut names = std::fs::read_dir(&dump)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    let read = |name: &str| std::fs::read_to_string(dump.join(name)).unwrap();
+    let (first, second, fourth) = (read("01.ans"), read("02.ans"), read("04.ans"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    let expected = (1..=12).map(|i| format!("{i:02}.ans")).collect::<Vec<_>>();
+    assert_eq!(names, expected);
+    // Each file holds a frame of its own, without an empty line before it
+    assert!(first.starts_with("\x1b[38;2;0;50;50m"), "{first:?}");
+    assert!(second.starts_with("\x1b[38;2;100;50;50m"), "{second:?}");
+    assert_eq!(first, fourth);
+}
+
+#[test]
+fn raw_info() {
+    let dir = std::env::temp_dir().join(format!("ascii_art-{}-raw", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let frames = dir.join("frames.rgba");
+    // Two 4x2 frames, the second brighter
+    let pixels = (0..2u8)
+        .flat_map(|frame| (0..8).flat_map(move |_| [frame * 200, 100, 50, 255]))
+        .collect::<Vec<_>>();
+    std::fs::write(&frames, pixels).unwrap();
+    let info = |json: &str| {
+        let path = dir.join("info.json");
+        std::fs::write(&path, json).unwrap();
+        Command::new(env!("CARGO_BIN_EXE_ascii_art"))
+            .args([
+                frames.to_str().unwrap(),
+                "--raw-info",
+                path.to_str().unwrap(),
+            ])
+            .args(["--color", "truecolor"])
+            .output()
+            .unwrap()
+    };
+    let declared = info(r#"{"width": 4, "height": 2, "format": "rgba", "frame_bytes": 32}"#);
+    let mismatched = info(r#"{"width": 4, "height": 2, "frame_bytes": 32}"#);
+    let given = run(&[
+        frames.to_str().unwrap(),
+        "--raw",
+        "4x2",
+        "--raw-format",
+        "rgba",
+        "--color",
+        "truecolor",
+    ]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(declared.status.success());
+    assert!(!given.is_empty());
+    assert_eq!(declared.stdout, given);
+    // Taken as rgb24, four by two pixels make 24 bytes
+    assert_eq!(mismatched.status.code(), Some(1));
+    let error = String::from_utf8_lossy(&mismatched.stderr);
+    assert!(error.contains("declares 32 bytes per frame"), "{error}");
+    // Frames too large to hold are turned down before anything is allocated
+    let huge = Command::new(env!("CARGO_BIN_EXE_ascii_art"))
+        .args(["-", "--raw", "100000x100000"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(huge.status.code(), Some(1));
+    let error = String::from_utf8_lossy(&huge.stderr);
+    assert!(error.contains("take more than"), "{error}");
+}
+
+#[test]
+fn burn_index() {
+    let image = image_file("burn");
+    let export = |name: &str, extra: &[&str]| {
+        let path = image.with_file_name(format!("ascii_art-{}-{}.png", std::process::id(), name));
+        let mut args = vec![
+            image.to_str().unwrap(),
+            "--scale",
+            "1",
+            "--export-image",
+            path.to_str().unwrap(),
+        ];
+        args.extend_from_slice(extra);
+        run(&args);
+        let picture = image::open(&path).unwrap().into_rgb8();
+        std::fs::remove_file(&path).unwrap();
+        picture
+    };
+    let plain = export("plain", &[]);
+    let burned = export("burned", &["--burn-index"]);
+    std::fs::remove_file(&image).unwrap();
+    assert_eq!(plain.dimensions(), burned.dimensions());
+    let (width, height) = plain.dimensions();
+    let differs = |rows: std::ops::Range<u32>| {
+        rows.flat_map(|y| (0..width).map(move |x| (x, y)))
+            .any(|(x, y)| plain.get_pixel(x, y) != burned.get_pixel(x, y))
+    };
+    // The label takes the first line of cells and leaves the rest as it was
+    assert!(differs(0..height / 4));
+    assert!(!differs(height / 2..height));
+}
+
+#[test]
+fn montage() {
+    let dir = std::env::temp_dir().join(format!("ascii_art-{}-montage", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for i in 0..10u8 {
+        RgbImage::from_pixel(16, 8, Rgb([i * 25, 100, 100]))
+            .save(dir.join(format!("{i}.png")))
+            .unwrap();
+    }
+    let sheet = run(&[
+        dir.to_str().unwrap(),
+        "--montage",
+        "3x2",
+        "--scale",
+        "4",
+        "--color",
+        "truecolor",
+    ]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let sheet = String::from_utf8(sheet).unwrap();
+    // The red of each tile a line runs through, in order
+    let reds = |line: &str| {
+        let mut reds = line
+            .split("\x1b[38;2;")
+            .skip(1)
+            .map(|color| color.split(';').next().unwrap().to_string())
+            .collect::<Vec<_>>();
+        reds.dedup();
+        reds
+    };
+    let (top, bottom) = sheet.split_once("\n\x1b[39m").unwrap();
+    // Six of the ten frames, the first and the last among them, with a row of blank
+    // cells between the two rows of tiles
+    for line in top.lines() {
+        assert_eq!(reds(line), ["0", "25", "75"], "{line:?}");
+    }
+    for line in bottom.lines().skip(1) {
+        assert_eq!(reds(line), ["125", "175", "225"], "{line:?}");
+    }
+    assert_eq!(top.lines().count(), bottom.lines().skip(1).count());
+
+    // A grid too large to lay out is turned down rather than overflowing
+    let output = Command::new(env!("CARGO_BIN_EXE_ascii_art"))
+        .args([".", "--montage", "70000x70000"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
+
+/// Stands in for ffprobe and ffmpeg with scripts in `dir`, reporting a one second
+/// 4x2 video and writing `ppm` for its frames. Each start of ffmpeg is counted in
+/// `dir/starts`.
+#[cfg(unix)]
+fn fake_ffmpeg(dir: &std::path::Path, ppm: &str) {
+    use std::os::unix::fs::PermissionsExt;
+    let script = |name: &str, body: String| {
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    };
+    script(
+        "ffprobe",
+        "printf 'width=4\\nheight=2\\nduration=1.0\\n'".into(),
+    );
+    let starts = dir.join("starts");
+    script(
+        "ffmpeg",
+        format!("echo >> {}\nprintf '{ppm}'", starts.display()),
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn broken_video_frames() {
+    let dir = std::env::temp_dir().join(format!("ascii_art-{}-video", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // A header claiming more pixels than could be held, then nothing
+    fake_ffmpeg(&dir, "P6 100000 100000 255\\n");
+    let video = dir.join("clip.mp4");
+    std::fs::write(&video, b"").unwrap();
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap());
+    let play = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_ascii_art"))
+            .args([video.to_str().unwrap(), "--fps", "3", "--loop", "2"])
+            .args(extra)
+            .env("PATH", &path)
+            .output()
+            .unwrap()
+    };
+    let strict = play(&["--strict"]);
+    let lenient = play(&[]);
+    let starts = std::fs::read_to_string(dir.join("starts")).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(strict.status.code(), Some(3));
+    // Every frame fails without panicking, and is only tried once over both loops
+    assert!(lenient.status.success());
+    let warnings = String::from_utf8_lossy(&lenient.stderr);
+    assert_eq!(
+        warnings.matches("malformed PPM header").count(),
+        3,
+        "{warnings}"
+    );
+    assert_eq!(starts.lines().count(), 1 + 3);
+}
+
+#[test]
+fn config_file() {
+    let image = image_file("config");
+    let config = image.with_extension("toml");
+    let with_config = |toml: &str, args: &[&str]| {
+        std::fs::write(&config, toml).unwrap();
+        let mut all = vec![
+            image.to_str().unwrap(),
+            "--config",
+            config.to_str().unwrap(),
+        ];
+        all.extend_from_slice(args);
+        Command::new(env!("CARGO_BIN_EXE_ascii_art"))
+            .args(all)
+            .output()
+            .unwrap()
+    };
+    let from_file = with_config("scale = 4\ncolor = \"truecolor\"\n", &[]);
+    let overridden = with_config("scale = 4\ncolor = \"truecolor\"\n", &["--scale", "2"]);
+    // --braille conflicts with the file's half-block, which is dropped for it
+    let conflicting = with_config("half-block = true\n", &["--scale", "2", "--braille"]);
+    let unknown = with_config("scael = 4\n", &[]);
+    let image_arg = image.to_str().unwrap();
+    let defaults = run(&[image_arg]);
+    let given = |args: &[&str]| run(&[&[image_arg][..], args].concat());
+    let (scaled, rescaled) = (
+        given(&["--scale", "4", "--color", "truecolor"]),
+        given(&["--scale", "2", "--color", "truecolor"]),
+    );
+    let braille = given(&["--scale", "2", "--braille"]);
+    std::fs::remove_file(&image).unwrap();
+    std::fs::remove_file(&config).unwrap();
+    // The file wins over the defaults
+    assert!(from_file.status.success());
+    assert_eq!(from_file.stdout, scaled);
+    assert_ne!(from_file.stdout, defaults);
+    // The command line wins over the file, for the options it gives and those they
+    // conflict with
+    assert_eq!(overridden.stdout, rescaled);
+    assert!(conflicting.status.success(), "{conflicting:?}");
+    assert_eq!(conflicting.stdout, braille);
+    // A misspelt key is an error naming it rather than quietly left out
+    assert_eq!(unknown.status.code(), Some(1));
+    let error = String::from_utf8_lossy(&unknown.stderr);
+    assert!(error.contains("unknown field `scael`"), "{error}");
+}
@@ -0,0 +1,88 @@
+//! Frames that take longer than usual to convert or draw, even longer than a frame
+//! time, don't push back the ones after them.
+
+use std::time::{Duration, Instant};
+
+use ascii_art::{frames_behind, next_deadline};
+
+/// Plays `frames` frames on a simulated clock, each taking as long as `work` says
+/// to get ready before it's shown, sleeping until it's due when there's time left.
+/// The first frame is scheduled off `start`. Returns when the last frame went up
+/// and the frames skipped on the way.
+fn play(
+    start: Instant,
+    frames: u32,
+    frame_time: Duration,
+    skip: bool,
+    work: impl Fn(u32) -> Duration,
+) -> (Instant, u32) {
+    let (mut clock, mut deadline, mut skipped) = (start, None, 0);
+    let mut index = 0;
+    while index < frames {
+        clock += work(index);
+        let due = next_deadline(deadline, start, frame_time);
+        clock = clock.max(due);
+        deadline = Some(due);
+        let behind = frames_behind(due, clock, frame_time);
+        if skip && behind > 0 {
+            skipped += behind;
+            index += behind;
+            deadline = Some(due + frame_time * behind);
+        }
+        index += 1;
+    }
+    (clock, skipped)
+}
+
+#[test]
+fn no_drift() {
+    let start = Instant::now();
+    let frame_time = Duration::from_millis(40);
+    // Anywhere from no time at all to most of a frame, and now and then a frame and
+    // a half, which the frames after it make up for
+    let work = |i: u32| match i % 17 {
+        0 => frame_time * 3 / 2,
+        n => frame_time * (n % 5) / 5,
+    };
+    let (end, skipped) = play(start, 1000, frame_time, false, work);
+    assert_eq!(skipped, 0);
+    let expected = frame_time * 1000;
+    let elapsed = end - start;
+    assert!(
+        elapsed >= expected && elapsed - expected <= frame_time,
+        "{elapsed:?} for {expected:?}"
+    );
+}
+
+#[test]
+fn skipped_frames_keep_to_the_clock() {
+    let start = Instant::now();
+    let frame_time = Duration::from_millis(40);
+    // Every tenth frame takes three and a half frame times
+    let work = |i: u32| match i % 10 {
+        0 => frame_time * 7 / 2,
+        _ => Duration::ZERO,
+    };
+    let (end, skipped) = play(start, 1000, frame_time, true, work);
+    assert!(skipped > 0);
+    let elapsed = end - start;
+    let expected = frame_time * 1000;
+    assert!(
+        elapsed >= expected - frame_time && elapsed <= expected + frame_time * 4,
+        "{elapsed:?} for {expected:?}"
+    );
+}
+
+#[test]
+fn deadlines() {
+    let now = Instant::now();
+    let frame_time = Duration::from_millis(10);
+    assert_eq!(next_deadline(None, now, frame_time), now + frame_time);
+    assert_eq!(
+        next_deadline(Some(now), now + frame_time * 5, frame_time),
+        now + frame_time
+    );
+    assert_eq!(frames_behind(now, now, frame_time), 0);
+    assert_eq!(frames_behind(now + frame_time, now, frame_time), 0);
+    assert_eq!(frames_behind(now, now + frame_time * 5 / 2, frame_time), 2);
+}
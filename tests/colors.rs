@@ -0,0 +1,39 @@
+//! The color mode each terminal gets from what it advertises.
+
+use ascii_art::{terminal_caps, ColorMode};
+
+fn name(mode: ColorMode) -> &'static str {
+    match mode {
+        ColorMode::Truecolor => "truecolor",
+        ColorMode::Ansi256 => "ansi256",
+        ColorMode::Mono => "mono",
+    }
+}
+
+#[test]
+fn advertised_modes() {
+    let cases = [
+        (("truecolor", "xterm-256color"), "truecolor"),
+        (("24bit", "xterm"), "truecolor"),
+        (("truecolor", ""), "truecolor"),
+        (("", "xterm-direct"), "truecolor"),
+        (("", "iterm2-truecolor"), "truecolor"),
+        (("", "xterm-256color"), "ansi256"),
+        (("", "screen-256color"), "ansi256"),
+        (("", "xterm"), "ansi256"),
+        // COLORTERM only counts when it says so exactly
+        (("yes", "xterm-256color"), "ansi256"),
+        (("", "dumb"), "mono"),
+        // A dumb terminal that advertises 24-bit colors anyway gets them
+        (("truecolor", "dumb"), "truecolor"),
+        // Nothing set at all is taken as a terminal with the palette
+        (("", ""), "ansi256"),
+    ];
+    for ((colorterm, term), expected) in cases {
+        assert_eq!(
+            name(terminal_caps(colorterm, term)),
+            expected,
+            "COLORTERM={colorterm:?} TERM={term:?}"
+        );
+    }
+}
@@ -0,0 +1,30 @@
+//! Frames drawn back into pictures with `rasterize`.
+
+use ascii_art::{default_font, rasterize, render_frame, ColorMode, FrameData, RenderOptions};
+use image::{DynamicImage, Rgb, RgbImage};
+
+#[test]
+fn two_by_two() {
+    let font = default_font();
+    // At 16 pixels, a column of DejaVu Sans Mono is as wide as its advance rounded
+    // up, a row as tall as its lines
+    let (column_width, line_height) = (9, 16);
+    let img = DynamicImage::ImageRgb8(RgbImage::from_fn(2, 2, |x, y| {
+        Rgb([x as u8 * 255, y as u8 * 255, 128])
+    }));
+    for pixel_width in [1, 2] {
+        let options = RenderOptions::builder()
+            .color_mode(ColorMode::Truecolor)
+            .pixel_width(pixel_width)
+            .build()
+            .unwrap();
+        let mut frame = FrameData::new(2, 2);
+        render_frame(&img, &mut frame, None, &options);
+        let picture = rasterize(&frame, &options, &font, 16.0);
+        // Each cell takes `pixel_width` columns
+        assert_eq!(
+            picture.dimensions(),
+            (2 * pixel_width * column_width, 2 * line_height)
+        );
+    }
+}
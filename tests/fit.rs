@@ -0,0 +1,104 @@
+//! Frames fitted to terminals too small to hold them, as reported mid-resize or
+//! where there's no terminal at all, come out a single cell rather than panicking.
+//! Rows kept free for the status line and the like are taken from the frame, and
+//! cells that aren't twice as tall as wide stretch the frame to keep its shape.
+
+use ascii_art::{
+    fit_cells, render_frame, Align, FrameData, Layout, RenderOptions, RenderOptionsBuilder,
+};
+use image::{DynamicImage, RgbImage};
+
+fn options() -> RenderOptions {
+    RenderOptions::builder().block_rows(1).build().unwrap()
+}
+
+#[test]
+fn no_terminal() {
+    assert_eq!(fit_cells((640, 480), (0, 0), &options()), (1, 1));
+}
+
+#[test]
+fn single_cell_terminal() {
+    assert_eq!(fit_cells((640, 480), (1, 1), &options()), (1, 1));
+    let braille = RenderOptions::builder().braille(true).build().unwrap();
+    assert_eq!(fit_cells((640, 480), (1, 1), &braille), (1, 1));
+}
+
+#[test]
+fn empty_source() {
+    assert_eq!(fit_cells((0, 0), (80, 24), &options()), (1, 1));
+    assert_eq!(fit_cells((0, 0), (0, 0), &options()), (1, 1));
+}
+
+#[test]
+fn single_cell_frame() {
+    let options = options();
+    let (width, height) = fit_cells((640, 480), (0, 0), &options);
+    let mut frame = FrameData::new(width, height);
+    frame.origin = Align::Center.origin((width, height), (0, 0), &options);
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(640, 480, [255; 3].into()));
+    render_frame(&img, &mut frame, None, &options);
+    assert_eq!(frame.cells.len(), 1);
+    assert!(frame.data.starts_with(b"\x1b[0m\x1b[1;1H"));
+}
+
+#[test]
+fn empty_frame() {
+    let options = options();
+    let mut frame = FrameData::new(0, 0);
+    let img = DynamicImage::ImageRgb8(RgbImage::new(0, 0));
+    render_frame(&img, &mut frame, None, &options);
+    assert!(frame.cells.is_empty());
+}
+
+#[test]
+fn status_row() {
+    let options = options();
+    let full = Layout::default().fit((100, 100), (80, 24), &options);
+    let status = Layout { top: 0, bottom: 1 }.fit((100, 100), (80, 24), &options);
+    assert_eq!(full.1, 24);
+    assert_eq!(status.1, 23);
+}
+
+#[test]
+fn reserved_top_row() {
+    let options = options();
+    let layout = Layout { top: 1, bottom: 0 };
+    let (width, height) = layout.fit((100, 100), (80, 24), &options);
+    assert_eq!(height, 23);
+    let mut frame = FrameData::new(width, height);
+    frame.origin = layout.origin(Align::TopLeft, (width, height), (80, 24), &options);
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(100, 100, [255; 3].into()));
+    render_frame(&img, &mut frame, None, &options);
+    // Every row moves down by the one kept free
+    assert!(frame.data.starts_with(b"\x1b[0m\x1b[2;1H"));
+    assert!(!frame.data.windows(6).any(|escape| escape == b"\x1b[1;1H"));
+    assert!(frame.data.windows(7).any(|escape| escape == b"\x1b[24;1H"));
+}
+
+#[test]
+fn tiny_terminal_with_reserved_rows() {
+    let layout = Layout { top: 2, bottom: 2 };
+    assert_eq!(layout.frame_rows(3), 0);
+    assert_eq!(layout.fit((640, 480), (10, 3), &options()), (1, 1));
+}
+
+#[test]
+fn cell_aspect() {
+    let fit = |options: RenderOptionsBuilder| {
+        fit_cells((640, 480), (200, 100), &options.build().unwrap())
+    };
+    let builder = RenderOptions::builder();
+    assert_eq!(fit(builder), (100, 75));
+    // Taller cells take fewer rows for the same picture
+    let two_columns = builder.pixel_width(2);
+    assert_eq!(fit(two_columns.cell_aspect(2.5)), (100, 60));
+    assert_eq!(fit(two_columns.cell_aspect(4.0)), (100, 37));
+    // Unless told otherwise a pixel takes the ratio rounded in columns, three of them
+    // take a little more than 2.5 so the rows stretch to keep up
+    assert_eq!(fit(builder.cell_aspect(2.5)), (66, 59));
+    assert_eq!(fit(builder.cell_aspect(1.0)), (133, 100));
+    // Braille dots are stretched the same way
+    assert_eq!(fit(builder.braille(true)), (200, 75));
+    assert_eq!(fit(builder.braille(true).cell_aspect(2.5)), (200, 60));
+}
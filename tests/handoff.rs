@@ -0,0 +1,88 @@
+//! Frames passed through a `handoff` between two threads, the way the player passes
+//! them from the thread converting them to the one showing them.
+
+use std::{thread, time::Duration};
+
+use ascii_art::{handoff, render_frame, ColorMode, FrameData, RenderOptions};
+use image::{DynamicImage, Rgb, RgbImage};
+
+/// Color of frame `index`, each different from the one before it.
+fn color(index: usize) -> [u8; 3] {
+    let i = index as u8;
+    [
+        i.wrapping_mul(37),
+        255 - i.wrapping_mul(11),
+        i.wrapping_mul(101),
+    ]
+}
+
+/// Converts `count` frames of one color each on a thread of their own, handed over
+/// `lookahead` ahead of the frame being looked at, and checks that each comes whole,
+/// once and in order.
+fn pass_frames(count: usize, lookahead: usize) {
+    let (width, height) = (12, 6);
+    // A block of rows per row, so the cells of a frame are written from several
+    // threads at once
+    let options = RenderOptions::builder()
+        .color_mode(ColorMode::Truecolor)
+        .pixel_width(1)
+        .block_rows(1)
+        .build()
+        .unwrap();
+    let (producer, consumer) = handoff(lookahead, (width, height));
+    let converting = thread::spawn(move || {
+        for index in 0..count {
+            let mut back = producer
+                .next_buffer()
+                .expect("frames are looked at to the end");
+            let img = RgbImage::from_pixel(width, height, Rgb(color(index)));
+            render_frame(&DynamicImage::ImageRgb8(img), &mut back, None, &options);
+            back.index = index;
+            assert!(producer.hand_over(back));
+        }
+    });
+    let mut shown = 0;
+    while let Ok(front) = consumer.next_frame(Duration::from_secs(10)) {
+        assert_eq!(front.index, shown, "lookahead {lookahead}");
+        assert_uniform(&front, color(shown));
+        // Now and then the frame on screen is held a while, for the producer to
+        // catch up and fill every buffer it has
+        if shown % 97 == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        shown += 1;
+        consumer.give_back(front);
+    }
+    converting.join().unwrap();
+    // The frames handed over before the producer was done are all there
+    assert_eq!(shown, count, "lookahead {lookahead}");
+}
+
+/// Checks that every cell of `frame`, and every color its escapes set, is `color`.
+fn assert_uniform(frame: &FrameData, [r, g, b]: [u8; 3]) {
+    assert!(
+        frame.cells.iter().all(|cell| *cell == frame.cells[0]),
+        "frame {} mixes cells",
+        frame.index
+    );
+    let data = String::from_utf8(frame.data.clone()).unwrap();
+    let own = format!("{r};{g};{b}m");
+    let colors = data.split("\x1b[38;2;").skip(1).collect::<Vec<_>>();
+    assert!(
+        !colors.is_empty() && colors.iter().all(|part| part.starts_with(&own)),
+        "frame {}: {data:?}",
+        frame.index
+    );
+}
+
+#[test]
+fn frames_are_never_torn() {
+    pass_frames(200, 2);
+}
+
+#[test]
+fn soak() {
+    for lookahead in [1, 2, 4, 8] {
+        pass_frames(5000, lookahead);
+    }
+}
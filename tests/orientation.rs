@@ -0,0 +1,92 @@
+//! JPEGs are turned upright by the orientation tag in their EXIF data, as each of
+//! its eight values asks.
+
+use std::path::PathBuf;
+
+use ascii_art::open_source;
+use image::{codecs::jpeg::JpegEncoder, DynamicImage, GenericImageView, Rgb, RgbImage};
+
+/// A 16x8 JPEG, black but for its white top left quarter, tagged with `orientation`.
+fn jpeg(orientation: u16) -> PathBuf {
+    let img = RgbImage::from_fn(16, 8, |x, y| match (x, y) {
+        (0..8, 0..4) => Rgb([255; 3]),
+        _ => Rgb([0; 3]),
+    });
+    let mut encoded = Vec::new();
+    JpegEncoder::new_with_quality(&mut encoded, 100)
+        .encode_image(&img)
+        .unwrap();
+    // An APP1 segment holding a little-endian TIFF header and a single IFD entry
+    let mut exif = b"Exif\0\0II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0".to_vec();
+    exif.extend_from_slice(&orientation.to_le_bytes());
+    exif.extend_from_slice(&[0; 6]);
+    let mut segment = vec![0xff, 0xe1];
+    segment.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+    segment.extend_from_slice(&exif);
+    // Right after the start of image marker
+    encoded.splice(2..2, segment);
+    let path = std::env::temp_dir().join(format!(
+        "ascii_art-{}-orientation-{}.jpg",
+        std::process::id(),
+        orientation
+    ));
+    std::fs::write(&path, encoded).unwrap();
+    path
+}
+
+/// The frame decoded from a JPEG tagged with `orientation`, turned or not.
+fn decoded(orientation: u16, auto_rotate: bool) -> ((u32, u32), DynamicImage) {
+    let path = jpeg(orientation);
+    let (frames, _) = open_source(&path, 10, &[]).unwrap();
+    let mut frames = frames.auto_rotate(auto_rotate);
+    let dimensions = frames.dimensions().unwrap();
+    let frame = frames.frame(0).unwrap().unwrap().into_owned();
+    std::fs::remove_file(&path).unwrap();
+    (dimensions, frame)
+}
+
+/// Which corner the white quarter ends up in, as (right, bottom).
+fn white_corner(img: &DynamicImage) -> (bool, bool) {
+    let (width, height) = img.dimensions();
+    let corners = [(false, false), (true, false), (false, true), (true, true)];
+    let lit = corners
+        .into_iter()
+        .filter(|&(right, bottom)| {
+            let x = if right { width - 2 } else { 1 };
+            let y = if bottom { height - 2 } else { 1 };
+            img.get_pixel(x, y).0[0] > 128
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(lit.len(), 1, "{:?}", lit);
+    lit[0]
+}
+
+#[test]
+fn each_orientation() {
+    // Size after turning and where the top left corner went, for tags 1 to 8
+    let expected = [
+        ((16, 8), (false, false)),
+        ((16, 8), (true, false)),
+        ((16, 8), (true, true)),
+        ((16, 8), (false, true)),
+        ((8, 16), (false, false)),
+        ((8, 16), (true, false)),
+        ((8, 16), (true, true)),
+        ((8, 16), (false, true)),
+    ];
+    for (orientation, (size, corner)) in (1..).zip(expected) {
+        let (dimensions, frame) = decoded(orientation, true);
+        assert_eq!(dimensions, size, "orientation {}", orientation);
+        assert_eq!(frame.dimensions(), size, "orientation {}", orientation);
+        assert_eq!(white_corner(&frame), corner, "orientation {}", orientation);
+    }
+}
+
+#[test]
+fn no_auto_rotate() {
+    for orientation in 1..=8 {
+        let (dimensions, frame) = decoded(orientation, false);
+        assert_eq!(dimensions, (16, 8));
+        assert_eq!(white_corner(&frame), (false, false));
+    }
+}
@@ -0,0 +1,69 @@
+//! Colors of the xterm-256 palette picked for a cell in 256-color mode.
+
+use ascii_art::{render_frame, rgb_to_lab, ColorMode, FrameData, RenderOptions};
+use image::{DynamicImage, Rgb, RgbImage};
+
+/// Palette index a cell of `color` is drawn in, measuring the distance to the
+/// palette in CIELAB with `lab`.
+fn index(color: [u8; 3], lab: bool) -> u8 {
+    let options = RenderOptions::builder()
+        .color_mode(ColorMode::Ansi256)
+        .lab(lab)
+        .build()
+        .unwrap();
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, Rgb(color)));
+    let mut frame = FrameData::new(1, 1);
+    render_frame(&img, &mut frame, None, &options);
+    let data = String::from_utf8(frame.data).unwrap();
+    let (_, rest) = data
+        .split_once("\x1b[38;5;")
+        .unwrap_or_else(|| panic!("no 256-color escape in {data:?}"));
+    rest.split('m').next().unwrap().parse().unwrap()
+}
+
+#[test]
+fn lab_keeps_dark_green() {
+    let green = [20, 60, 20];
+    // sRGB distance settles on the dark gray 38, 38, 38, CIELAB on the cube's darkest
+    // green 0, 95, 0, which the eye finds the nearer of the two
+    assert_eq!(index(green, false), 235);
+    assert_eq!(index(green, true), 22);
+    let delta = |other: [u8; 3]| {
+        let ([l1, a1, b1], [l2, a2, b2]) = (rgb_to_lab(green), rgb_to_lab(other));
+        (l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)
+    };
+    assert!(delta([0, 95, 0]) < delta([38, 38, 38]));
+}
+
+#[test]
+fn cube_corners() {
+    let corners = [
+        ([0, 0, 0], 16),
+        ([0, 0, 255], 21),
+        ([0, 255, 0], 46),
+        ([0, 255, 255], 51),
+        ([255, 0, 0], 196),
+        ([255, 0, 255], 201),
+        ([255, 255, 0], 226),
+        ([255, 255, 255], 231),
+    ];
+    for (color, expected) in corners {
+        assert_eq!(index(color, false), expected, "{color:?}");
+    }
+}
+
+#[test]
+fn gray_ramp() {
+    // From 8 to 238 in steps of 10, grays in between going to the nearer step
+    let grays = [
+        (8, 232),
+        (12, 232),
+        (14, 233),
+        (128, 244),
+        (200, 251),
+        (238, 255),
+    ];
+    for (gray, expected) in grays {
+        assert_eq!(index([gray; 3], false), expected, "{gray}");
+    }
+}
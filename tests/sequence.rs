@@ -0,0 +1,68 @@
+//! The frames of a folder play in the order their names read in, numbers by their
+//! value rather than digit by digit, whatever the folder itself is called.
+
+use ascii_art::open_source;
+use image::{Rgb, RgbImage};
+
+#[test]
+fn natural_order() {
+    let sorted = [
+        "0.png",
+        "1.png",
+        "01.png",
+        "2.png",
+        "9.png",
+        "10.png",
+        "010.png",
+        "11.png",
+        "100.png",
+        "a2.png",
+        "a10.png",
+        "frame_1_2.png",
+        "frame_1_10.png",
+        "frame_2_1.png",
+    ];
+    let dir = std::env::temp_dir().join(format!("ascii_art-{}-sequence", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // Written out of order, though the folder lists them in an order of its own
+    for i in [7, 12, 0, 3, 10, 5, 13, 1, 9, 4, 11, 2, 8, 6] {
+        RgbImage::from_pixel(1, 1, Rgb([0; 3]))
+            .save(dir.join(sorted[i]))
+            .unwrap();
+    }
+    let (frames, _) = open_source(&dir, 10, &[]).unwrap();
+    let names = frames
+        .paths()
+        .unwrap()
+        .iter()
+        .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(names, sorted);
+}
+
+#[test]
+fn folder_named_like_an_animation() {
+    // A folder is a folder of frames, whatever its name says
+    for extension in ["gif", "webp"] {
+        let dir = std::env::temp_dir().join(format!(
+            "ascii_art-{}-shots.{extension}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["1.png", "2.png"] {
+            RgbImage::from_pixel(1, 1, Rgb([0; 3]))
+                .save(dir.join(name))
+                .unwrap();
+        }
+        let opened = open_source(&dir, 10, &[]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let (frames, loops) = opened.unwrap();
+        assert_eq!(
+            frames.paths().map(|paths| paths.len()),
+            Some(2),
+            "{extension}"
+        );
+        assert_eq!(loops, None);
+    }
+}
@@ -0,0 +1,657 @@
+//! Pins the escape sequences frames are drawn with to the golden files in
+//! `tests/snapshots`. Run with `UPDATE_SNAPSHOTS=1` to write them anew after a change
+//! that's meant to alter the output, and look over the diff.
+
+use std::path::PathBuf;
+
+use ascii_art::{
+    crossfade, render_frame, write_lines, Align, ColorMode, Filter, FrameData, Luma, Ramp,
+    RenderOptions, Sample,
+};
+use image::{DynamicImage, Rgba, RgbaImage};
+
+const OPTIONS: RenderOptions = RenderOptions {
+    ramp: Ramp::Short,
+    color: ColorMode::Truecolor,
+    filter: Filter::Nearest,
+    sample: Sample::Point,
+    pixel_width: 2,
+    cell_aspect: 2.0,
+    braille: false,
+    half_block: false,
+    dither: false,
+    edges: false,
+    edge_threshold: None,
+    diff: false,
+    gamma: false,
+    luma: None,
+    lab: false,
+    color_levels: None,
+    posterize: None,
+    contrast: 1.0,
+    brightness: 0.0,
+    background: None,
+    invert: false,
+    // A single block, so the output doesn't depend on the number of threads
+    block_rows: Some(u32::MAX),
+    graphics: None,
+    seed: 0,
+    crop: None,
+};
+
+/// An 8x8 image with a gradient in each channel, a transparent corner and a white
+/// border on the right.
+fn image() -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 8, |x, y| match (x, y) {
+        (0..=1, 0..=1) => Rgba([0, 0, 0, 0]),
+        (7, _) => Rgba([255, 255, 255, 255]),
+        _ => Rgba([(x * 36) as u8, (y * 36) as u8, ((x + y) * 18) as u8, 255]),
+    }))
+}
+
+/// Escape sequences drawing the image with `options`.
+fn render(options: &RenderOptions) -> String {
+    let (cell_width, cell_height) = options.cell_pixels();
+    let mut frame = FrameData::new(8 / cell_width, 8 / cell_height);
+    render_frame(&image(), &mut frame, None, options);
+    escaped(frame.data)
+}
+
+/// `bytes` with the escape byte spelled out, so the golden files read as text.
+fn escaped(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes)
+        .expect("frames are drawn in UTF-8")
+        .replace('\x1b', "\\e")
+}
+
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(name);
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("failed to read {}: {}", path.display(), err));
+    assert!(
+        actual == expected,
+        "{name} changed, rerun with UPDATE_SNAPSHOTS=1 if that's intended\n\
+         expected: {expected}\n  actual: {actual}"
+    );
+}
+
+#[test]
+fn truecolor() {
+    assert_snapshot("truecolor.txt", &render(&OPTIONS));
+}
+
+#[test]
+fn ansi256() {
+    let options = RenderOptions {
+        color: ColorMode::Ansi256,
+        ..OPTIONS
+    };
+    assert_snapshot("ansi256.txt", &render(&options));
+}
+
+#[test]
+fn mono() {
+    let options = RenderOptions {
+        color: ColorMode::Mono,
+        ramp: Ramp::Long,
+        ..OPTIONS
+    };
+    assert_snapshot("mono.txt", &render(&options));
+}
+
+#[test]
+fn half_block() {
+    let options = RenderOptions {
+        half_block: true,
+        ..OPTIONS
+    };
+    assert_snapshot("half_block.txt", &render(&options));
+}
+
+#[test]
+fn half_block_two_tone() {
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(1, 2, |_, y| match y {
+        0 => Rgba([255, 0, 0, 255]),
+        _ => Rgba([0, 0, 255, 255]),
+    }));
+    let options = RenderOptions {
+        half_block: true,
+        ..OPTIONS
+    };
+    let mut frame = FrameData::new(1, 1);
+    render_frame(&img, &mut frame, None, &options);
+    assert_eq!(
+        escaped(frame.data),
+        "\\e[0m\\e[1;1H\\e[38;2;255;0;0m\\e[48;2;0;0;255m▀\\e[0m"
+    );
+}
+
+#[test]
+fn braille() {
+    let options = RenderOptions {
+        braille: true,
+        ..OPTIONS
+    };
+    assert_snapshot("braille.txt", &render(&options));
+}
+
+#[test]
+fn dither() {
+    let options = RenderOptions {
+        dither: true,
+        ..OPTIONS
+    };
+    assert_snapshot("dither.txt", &render(&options));
+}
+
+#[test]
+fn smaller_than_grid() {
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(3, 2, |x, y| {
+        Rgba([(x * 120) as u8, (y * 250) as u8, 60, 255])
+    }));
+    let mut frame = FrameData::new(8, 8);
+    render_frame(&img, &mut frame, None, &OPTIONS);
+    assert_snapshot("smaller_than_grid.txt", &escaped(frame.data));
+}
+
+#[test]
+fn solid_row_colored_once() {
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 1, Rgba([40, 80, 120, 255])));
+    let mut frame = FrameData::new(8, 1);
+    render_frame(&img, &mut frame, None, &OPTIONS);
+    let data = escaped(frame.data);
+    assert_eq!(data.matches("\\e[38;").count(), 1, "{data}");
+}
+
+#[test]
+fn color_levels() {
+    let options = RenderOptions {
+        color_levels: Some(2),
+        ..OPTIONS
+    };
+    assert_snapshot("color_levels.txt", &render(&options));
+}
+
+#[test]
+fn posterize() {
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(256, 1, |x, _| {
+        Rgba([x as u8, x as u8, x as u8, 255])
+    }));
+    let options = RenderOptions {
+        pixel_width: 1,
+        posterize: Some(2),
+        ..OPTIONS
+    };
+    let mut frame = FrameData::new(256, 1);
+    render_frame(&img, &mut frame, None, &options);
+    // Each quarter of the inputs comes out as one level, written once where it starts
+    let data = escaped(frame.data);
+    let colors = data
+        .split("\\e[38;2;")
+        .skip(1)
+        .map(|escape| escape.split_once('m').unwrap().0)
+        .collect::<Vec<_>>();
+    assert_eq!(colors, ["0;0;0", "85;85;85", "170;170;170", "255;255;255"]);
+}
+
+#[test]
+fn seeded_output_repeats() {
+    let options = RenderOptions {
+        dither: true,
+        seed: 7,
+        ..OPTIONS
+    };
+    // Pinned too, so a renderer drawing on anything but the seed can't pass by
+    // giving the same bytes twice in one run. Nothing is picked at random yet, so
+    // the frame is the one dithered with the default seed
+    let first = render(&options);
+    assert_eq!(first, render(&options));
+    assert_snapshot("dither.txt", &first);
+}
+
+#[test]
+fn cropped() {
+    // Red on the left, blue on the right, and a green row along the bottom
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 8, |x, y| match (x, y) {
+        (_, 7) => Rgba([0, 255, 0, 255]),
+        (0..4, _) => Rgba([255, 0, 0, 255]),
+        _ => Rgba([0, 0, 255, 255]),
+    }));
+    let options = RenderOptions {
+        pixel_width: 1,
+        crop: Some((4, 0, 4, 7)),
+        ..OPTIONS
+    };
+    let mut frame = FrameData::new(4, 7);
+    render_frame(&img, &mut frame, None, &options);
+    let data = escaped(frame.data);
+    assert_eq!(data.matches("\\e[38;").count(), 1, "{data}");
+    assert!(data.contains("\\e[38;2;0;0;255m"), "{data}");
+}
+
+/// Colors of the frame `img` comes out as, in the order they're written.
+fn colors(img: &DynamicImage, options: &RenderOptions) -> Vec<String> {
+    let mut frame = FrameData::new(img.width(), img.height());
+    render_frame(img, &mut frame, None, options);
+    escaped(frame.data)
+        .split("\\e[38;2;")
+        .skip(1)
+        .map(|escape| escape.split_once('m').unwrap().0.to_string())
+        .collect()
+}
+
+#[test]
+fn crossfade_midpoint() {
+    let solid = |color| DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 2, Rgba(color)));
+    let (from, to) = (solid([200, 40, 0, 255]), solid([0, 80, 100, 255]));
+    let options = RenderOptions {
+        pixel_width: 1,
+        ..OPTIONS
+    };
+    let midpoint = crossfade(&from, &to, 0.5, false);
+    assert_eq!(colors(&midpoint, &options), ["100;60;50"]);
+    assert_eq!(
+        colors(&crossfade(&from, &to, 0.0, false), &options),
+        ["200;40;0"]
+    );
+    assert_eq!(
+        colors(&crossfade(&from, &to, 1.0, false), &options),
+        ["0;80;100"]
+    );
+}
+
+#[test]
+fn crossfade_lab() {
+    let solid = |color| DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 2, Rgba(color)));
+    let (black, white) = (solid([0, 0, 0, 255]), solid([255, 255, 255, 255]));
+    let options = RenderOptions {
+        pixel_width: 1,
+        ..OPTIONS
+    };
+    // Halfway in lightness is a darker gray than halfway in sRGB
+    let midpoint = crossfade(&black, &white, 0.5, true);
+    assert_eq!(colors(&midpoint, &options), ["119;119;119"]);
+    assert_eq!(
+        colors(&crossfade(&black, &white, 0.0, true), &options),
+        ["0;0;0"]
+    );
+    assert_eq!(
+        colors(&crossfade(&black, &white, 1.0, true), &options),
+        ["255;255;255"]
+    );
+}
+
+#[test]
+fn half_transparent_over_background() {
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 128])));
+    let options = RenderOptions {
+        pixel_width: 1,
+        background: Some([0, 0, 0]),
+        ..OPTIONS
+    };
+    assert_eq!(colors(&img, &options), ["128;128;128"]);
+}
+
+#[test]
+fn antialiased_edge() {
+    // White next to transparent black, scaled down so the edge between them is mixed
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 1, |x, _| match x {
+        0..2 => Rgba([255, 255, 255, 255]),
+        _ => Rgba([0, 0, 0, 0]),
+    }));
+    let options = RenderOptions {
+        pixel_width: 1,
+        filter: Filter::Triangle,
+        background: Some([0, 0, 255]),
+        ..OPTIONS
+    };
+    // The edge is white half covering the blue, not darkened by the black around it
+    let mut frame = FrameData::new(3, 1);
+    render_frame(&img, &mut frame, None, &options);
+    let data = escaped(frame.data);
+    assert!(data.contains("\\e[38;2;128;128;255m"), "{data}");
+}
+
+#[test]
+fn checkerboard_average() {
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 4, |x, y| match (x + y) % 2 {
+        0 => Rgba([0, 0, 0, 255]),
+        _ => Rgba([255, 255, 255, 255]),
+    }));
+    let options = RenderOptions {
+        pixel_width: 1,
+        ..OPTIONS
+    };
+    // Each cell covers 2x2 pixels, two black and two white. A color shared by all of
+    // them is only set once
+    let render = |sample| {
+        let mut frame = FrameData::new(4, 2);
+        render_frame(&img, &mut frame, None, &RenderOptions { sample, ..options });
+        escaped(frame.data)
+    };
+    let point = render(Sample::Point);
+    assert_eq!(point.matches("\\e[38;2;").count(), 1, "{point}");
+    assert!(point.contains("\\e[38;2;0;0;0m"), "{point}");
+    let average = render(Sample::Average);
+    assert_eq!(average.matches("\\e[38;2;").count(), 1, "{average}");
+    assert!(average.contains("\\e[38;2;128;128;128m"), "{average}");
+}
+
+#[test]
+fn luma_weights() {
+    let green = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([0, 255, 0, 255])));
+    let glyph = |luma| {
+        let options = RenderOptions {
+            color: ColorMode::Mono,
+            pixel_width: 1,
+            luma,
+            ..OPTIONS
+        };
+        let mut frame = FrameData::new(1, 1);
+        render_frame(&green, &mut frame, None, &options);
+        let data = escaped(frame.data);
+        data.trim_end_matches("\\e[0m").chars().last().unwrap()
+    };
+    // Green is 150 of 255 by Rec. 601, 182 by Rec. 709 and 85 on average
+    assert_eq!(glyph(None), glyph(Some(Luma::Rec601)));
+    assert_ne!(glyph(Some(Luma::Rec601)), glyph(Some(Luma::Rec709)));
+    assert_ne!(glyph(Some(Luma::Rec601)), glyph(Some(Luma::Average)));
+}
+
+#[test]
+fn edges_over_ramp() {
+    // Dark on the left, light on the right
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 4, |x, _| match x {
+        0..=3 => Rgba([64, 64, 64, 255]),
+        _ => Rgba([192, 192, 192, 255]),
+    }));
+    let options = RenderOptions {
+        color: ColorMode::Mono,
+        pixel_width: 1,
+        edge_threshold: Some(0.3),
+        ..OPTIONS
+    };
+    let mut frame = FrameData::new(8, 4);
+    render_frame(&img, &mut frame, None, &options);
+    let mut buf = Vec::new();
+    write_lines(&mut buf, &frame, &options);
+    let text = String::from_utf8(buf).unwrap();
+    let shade = |luma| {
+        let mut frame = FrameData::new(1, 1);
+        let pixel = RgbaImage::from_pixel(1, 1, Rgba([luma, luma, luma, 255]));
+        render_frame(&DynamicImage::ImageRgba8(pixel), &mut frame, None, &options);
+        let mut buf = Vec::new();
+        write_lines(&mut buf, &frame, &options);
+        String::from_utf8(buf).unwrap().chars().next().unwrap()
+    };
+    let (dark, light) = (shade(64), shade(192));
+    assert_ne!(dark, light);
+    // The flat halves are shaded, the step between them drawn as a line
+    let row = format!("{dark}{dark}{dark}||{light}{light}{light}");
+    for line in text.lines() {
+        assert_eq!(line, row, "{text}");
+    }
+}
+
+#[test]
+fn reset_around_frames() {
+    let options = RenderOptions {
+        diff: true,
+        ..OPTIONS
+    };
+    let mut frame = FrameData::new(4, 8);
+    render_frame(&image(), &mut frame, None, &options);
+    let data = escaped(frame.data.clone());
+    // Colors left set before the frame, or by it, don't carry over
+    assert!(data.starts_with("\\e[0m\\e["), "{data}");
+    assert!(data.ends_with("\\e[0m"), "{data}");
+    // Unless it draws nothing at all
+    let previous = frame.cells.clone();
+    render_frame(&image(), &mut frame, Some(&previous), &options);
+    assert!(frame.data.is_empty());
+}
+
+#[test]
+fn lines() {
+    let mut frame = FrameData::new(8, 8);
+    render_frame(&image(), &mut frame, None, &OPTIONS);
+    let mut buf = Vec::new();
+    write_lines(&mut buf, &frame, &OPTIONS);
+    assert_snapshot("lines.txt", &escaped(buf));
+}
+
+#[test]
+fn centered() {
+    let options = RenderOptions {
+        pixel_width: 1,
+        ..OPTIONS
+    };
+    let mut frame = FrameData::new(10, 10);
+    frame.origin = Align::Center.origin((10, 10), (30, 30), &options);
+    render_frame(&image(), &mut frame, None, &options);
+    let data = escaped(frame.data);
+    assert!(data.starts_with("\\e[0m\\e[11;11H"), "drawn from {data}");
+}
+
+#[test]
+fn first_cell_at_top_left() {
+    let mut frame = FrameData::new(4, 8);
+    render_frame(&image(), &mut frame, None, &OPTIONS);
+    let data = escaped(frame.data);
+    // Cursor positions count from 1, a frame at the origin starts at (1, 1)
+    assert!(data.starts_with("\\e[0m\\e[1;1H"), "drawn from {data}");
+}
+
+#[test]
+fn horizontal_edge() {
+    // Dark at the top, light at the bottom
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 8, |_, y| match y {
+        0..=3 => Rgba([64, 64, 64, 255]),
+        _ => Rgba([192, 192, 192, 255]),
+    }));
+    let options = RenderOptions {
+        color: ColorMode::Mono,
+        pixel_width: 1,
+        edges: true,
+        ..OPTIONS
+    };
+    let mut frame = FrameData::new(4, 8);
+    render_frame(&img, &mut frame, None, &options);
+    let mut buf = Vec::new();
+    write_lines(&mut buf, &frame, &options);
+    let text = String::from_utf8(buf).unwrap();
+    // The flat halves are left blank, the step between them drawn as a line across
+    let blank = "    ";
+    let lines = [blank, blank, blank, "----", "----", blank, blank, blank];
+    assert_eq!(text.lines().collect::<Vec<_>>(), lines, "{text}");
+}
+
+#[test]
+fn gamma() {
+    let glyph = |color: [u8; 3], gamma| {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+            1,
+            1,
+            Rgba([color[0], color[1], color[2], 255]),
+        ));
+        let options = RenderOptions {
+            color: ColorMode::Mono,
+            pixel_width: 1,
+            gamma,
+            ..OPTIONS
+        };
+        let mut frame = FrameData::new(1, 1);
+        render_frame(&img, &mut frame, None, &options);
+        let data = escaped(frame.data);
+        data.trim_end_matches("\\e[0m").chars().last().unwrap()
+    };
+    // Red is 76 of 255 by the Rec. 601 weights on its sRGB values, but 127 in linear
+    // light encoded back, where a mid-gray lands
+    assert_eq!(glyph([255, 0, 0], false), '-');
+    assert_eq!(glyph([255, 0, 0], true), '=');
+    assert_eq!(glyph([127; 3], false), '=');
+    // A gray is its own luminance either way
+    assert_eq!(glyph([127; 3], true), '=');
+}
+
+#[test]
+fn braille_dots() {
+    let glyph = |lit: fn(u32, u32) -> bool| {
+        let img = RgbaImage::from_fn(2, 4, |x, y| match lit(x, y) {
+            true => Rgba([255, 255, 255, 255]),
+            false => Rgba([0, 0, 0, 255]),
+        });
+        let options = RenderOptions {
+            color: ColorMode::Mono,
+            braille: true,
+            ..OPTIONS
+        };
+        let mut frame = FrameData::new(1, 1);
+        render_frame(&DynamicImage::ImageRgba8(img), &mut frame, None, &options);
+        let data = escaped(frame.data);
+        data.trim_end_matches("\\e[0m").chars().last().unwrap()
+    };
+    assert_eq!(glyph(|_, _| false), '\u{2800}');
+    assert_eq!(glyph(|_, _| true), '\u{28FF}');
+    // Dots 1 and 8, in the top left and bottom right corners
+    assert_eq!(glyph(|x, y| (x, y) == (0, 0)), '\u{2801}');
+    assert_eq!(glyph(|x, y| (x, y) == (1, 3)), '\u{2880}');
+}
+
+#[test]
+fn dithered_gradient() {
+    // A slow ramp from black to white, left to right
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(40, 8, |x, _| {
+        let luma = (x * 255 / 39) as u8;
+        Rgba([luma, luma, luma, 255])
+    }));
+    let glyphs = |dither| {
+        let options = RenderOptions {
+            color: ColorMode::Mono,
+            pixel_width: 1,
+            dither,
+            ..OPTIONS
+        };
+        let mut frame = FrameData::new(40, 8);
+        render_frame(&img, &mut frame, None, &options);
+        let mut buf = Vec::new();
+        write_lines(&mut buf, &frame, &options);
+        let text = String::from_utf8(buf).unwrap();
+        text.lines()
+            .map(|line| line.chars().collect::<Vec<_>>())
+            .collect::<Vec<_>>()
+    };
+    let histogram = |rows: &[Vec<char>]| {
+        let mut counts = std::collections::BTreeMap::new();
+        for &glyph in rows.iter().flatten() {
+            *counts.entry(glyph).or_insert(0) += 1;
+        }
+        counts
+    };
+    // Each column is a single glyph without dithering, in bands as wide as a step of
+    // the ramp. Dithering mixes neighbouring glyphs across the band instead
+    let (plain, dithered) = (glyphs(false), glyphs(true));
+    let mixed = |rows: &[Vec<char>]| (0..40).any(|x| rows.iter().any(|row| row[x] != rows[0][x]));
+    assert!(!mixed(&plain));
+    assert!(mixed(&dithered));
+    assert_ne!(histogram(&plain), histogram(&dithered));
+}
+
+#[test]
+fn columns_per_pixel() {
+    let row = |pixels: [u8; 3]| {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(3, 1, |x, _| {
+            let luma = pixels[x as usize];
+            Rgba([luma, luma, luma, 255])
+        }))
+    };
+    for pixel_width in 1..=4 {
+        let options = RenderOptions {
+            color: ColorMode::Mono,
+            pixel_width,
+            diff: true,
+            ..OPTIONS
+        };
+        let mut frame = FrameData::new(3, 1);
+        render_frame(&row([255, 255, 255]), &mut frame, None, &options);
+        let data = String::from_utf8(frame.data.clone()).unwrap();
+        let full = "@".repeat(3 * pixel_width as usize);
+        assert!(
+            data.contains(&format!("\x1b[1;1H{full}\x1b")),
+            "{pixel_width}: {data:?}"
+        );
+        let previous = frame.cells.clone();
+        // Only the last pixel changes, the cursor is moved past the pixels before it
+        // by as many columns as each glyph is repeated
+        render_frame(&row([255, 255, 0]), &mut frame, Some(&previous), &options);
+        let data = String::from_utf8(frame.data.clone()).unwrap();
+        let blank = " ".repeat(pixel_width as usize);
+        let expected = format!("\x1b[1;{}H{blank}\x1b[0m", 2 * pixel_width + 1);
+        assert!(data.ends_with(&expected), "{pixel_width}: {data:?}");
+    }
+}
+
+#[test]
+fn transparent_region() {
+    // Fully transparent on the left, red on the right
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 1, |x, _| match x {
+        0..2 => Rgba([0, 255, 0, 0]),
+        _ => Rgba([255, 0, 0, 255]),
+    }));
+    let draw = |background| {
+        let options = RenderOptions {
+            pixel_width: 1,
+            background,
+            ..OPTIONS
+        };
+        let mut frame = FrameData::new(4, 1);
+        render_frame(&img, &mut frame, None, &options);
+        escaped(frame.data)
+    };
+    // Left to the terminal's own background as blanks in its default color, whatever
+    // color the transparent pixels hold
+    assert_eq!(
+        draw(None),
+        "\\e[0m\\e[1;1H\\e[39m  \\e[38;2;255;0;0m--\\e[0m"
+    );
+    // Or drawn in the background color given
+    assert_eq!(
+        draw(Some([0, 0, 255])),
+        "\\e[0m\\e[1;1H\\e[38;2;0;0;255m..\\e[38;2;255;0;0m--\\e[0m"
+    );
+}
+
+#[test]
+fn mixed_width_ramp() {
+    // Half and full width glyphs, for the darkest pixels first
+    const GLYPHS: &[char] = &[' ', 'a', '中', '@'];
+    let ramp = Ramp::Custom(GLYPHS);
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 1, |x, _| {
+        let luma = (x * 85) as u8;
+        Rgba([luma, luma, luma, 255])
+    }));
+    let draw = |pixel_width| {
+        let options = RenderOptions {
+            ramp,
+            color: ColorMode::Mono,
+            pixel_width,
+            ..OPTIONS
+        };
+        let mut frame = FrameData::new(4, 1);
+        render_frame(&img, &mut frame, None, &options);
+        String::from_utf8(frame.data).unwrap()
+    };
+    // Two columns take a half width glyph twice and a full width one once
+    assert!(ramp.undrawable(2).is_empty());
+    assert!(draw(2).contains("  aa中@@"), "{:?}", draw(2));
+    // One column can't take a full width glyph, it's left blank so the cells after
+    // it stay in place
+    assert_eq!(ramp.undrawable(1), ['中']);
+    assert!(draw(1).contains(" a @"), "{:?}", draw(1));
+}
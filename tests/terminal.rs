@@ -0,0 +1,452 @@
+//! Runs the player on a pseudo-terminal, made by util-linux's `script`, and quits it
+//! with `q` like a user would, or lets it play to the end. Skipped where `script`
+//! isn't installed.
+#![cfg(target_os = "linux")]
+
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use image::{Rgb, RgbImage};
+
+/// A small colorful image in a file of its own, for the player to open.
+fn image_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("ascii_art-{}-{}.png", std::process::id(), name));
+    RgbImage::from_fn(16, 8, |x, y| Rgb([x as u8 * 16, y as u8 * 32, 128]))
+        .save(&path)
+        .unwrap();
+    path
+}
+
+/// Plays a small image with `args` and the status line on a 40x12 terminal until
+/// `q` is pressed, and returns everything written to the terminal, followed by the
+/// terminal settings once the player is gone. `None` without `script`.
+fn play(name: &str, args: &str) -> Option<String> {
+    if !has_script() {
+        return None;
+    }
+    let path = image_file(name);
+    let command = format!(
+        "stty cols 40 rows 12; {} {} --status {}; echo; stty -a",
+        env!("CARGO_BIN_EXE_ascii_art"),
+        path.display(),
+        args
+    );
+    let mut child = Command::new("script")
+        .args(["-qec", &command, "/dev/null"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let mut output = Vec::new();
+    let mut buf = [0; 4096];
+    // Quit once the first frame is on screen
+    while !output.windows(9).any(|window| window == b"frame 1/1") {
+        let read = stdout.read(&mut buf).unwrap();
+        assert!(read > 0, "{}", String::from_utf8_lossy(&output));
+        output.extend_from_slice(&buf[..read]);
+    }
+    child.stdin.take().unwrap().write_all(b"q").unwrap();
+    stdout.read_to_end(&mut output).unwrap();
+    assert!(child.wait().unwrap().success());
+    std::fs::remove_file(&path).unwrap();
+    Some(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Plays the frames in `path` with `args` on a 40x12 terminal until they run out,
+/// and returns everything written to the terminal, followed by the terminal
+/// settings once the player is gone. `None` without `script`.
+fn play_through(path: &Path, args: &str) -> Option<String> {
+    if !has_script() {
+        return None;
+    }
+    let command = format!(
+        "stty cols 40 rows 12; {} {} {}; echo; stty -a",
+        env!("CARGO_BIN_EXE_ascii_art"),
+        path.display(),
+        args
+    );
+    // Nothing is typed, but the input stays open: `script` may end the session early
+    // once it reads to the end of it
+    let mut child = Command::new("script")
+        .args(["-qec", &command, "/dev/null"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let input = child.stdin.take();
+    let mut output = Vec::new();
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_end(&mut output)
+        .unwrap();
+    drop(input);
+    assert!(child.wait().unwrap().success());
+    Some(String::from_utf8_lossy(&output).into_owned())
+}
+
+fn has_script() -> bool {
+    let found = Command::new("script").arg("--version").output().is_ok();
+    if !found {
+        eprintln!("script isn't installed, skipping");
+    }
+    found
+}
+
+/// Whether the settings `stty -a` printed last are those of a terminal out of raw
+/// mode, with lines edited and echoed.
+fn cooked(output: &str) -> bool {
+    let settings = &output[output.rfind("speed").unwrap()..];
+    let flags = settings.split_whitespace().collect::<Vec<_>>();
+    ["icanon", "echo", "icrnl", "opost"]
+        .iter()
+        .all(|flag| flags.contains(flag))
+}
+
+#[test]
+fn clears_on_exit() {
+    let Some(output) = play("clear", "") else {
+        return;
+    };
+    assert!(output.contains("\x1b[?1049h"));
+    assert!(output.contains("\x1b[?1049l"));
+    assert!(cooked(&output), "{}", output);
+}
+
+#[test]
+fn keep_last() {
+    let Some(output) = play("keep", "--keep-last") else {
+        return;
+    };
+    assert!(!output.contains("\x1b[?1049"));
+    // The cursor ends up on the status line and goes on to the line below it
+    let status = output.find("\x1b[0m\x1b[2Kframe").unwrap();
+    let moved = &output[output[..status].rfind("\x1b[").unwrap()..status];
+    assert!(
+        output.contains(&format!("\x1b[0m{moved}\r\n")),
+        "{:?}",
+        output
+    );
+    assert!(cooked(&output), "{}", output);
+}
+
+#[test]
+fn title() {
+    let Some(output) = play("title", "--title") else {
+        return;
+    };
+    let name = format!("ascii_art-{}-title.png", std::process::id());
+    let set = output
+        .find(&format!("\x1b]0;{name} - frame 1/1\x07"))
+        .unwrap();
+    // Saved before it's first set, and put back on exit
+    assert!(output[..set].contains("\x1b[22;0t"));
+    assert!(output[set..].contains("\x1b[23;0t"));
+}
+
+#[test]
+fn once() {
+    let path = image_file("once");
+    let output = play_through(&path, "--once");
+    std::fs::remove_file(&path).unwrap();
+    let Some(output) = output else {
+        return;
+    };
+    // Written in place as lines in color, the terminal left as it was
+    assert!(!output.contains("\x1b[?1049h"));
+    assert!(!output.contains("\x1b[?25l"));
+    assert!(output.contains("\x1b[38;"));
+    assert!(cooked(&output), "{}", output);
+}
+
+#[test]
+fn lookahead() {
+    let dir = std::env::temp_dir().join(format!("ascii_art-{}-lookahead", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for i in 0..8u8 {
+        RgbImage::from_pixel(16, 8, Rgb([i * 30, 0, 0]))
+            .save(dir.join(format!("{i}.png")))
+            .unwrap();
+    }
+    // Most frames ready when the player took one, as --stats reports it
+    let most_ahead = |lookahead: u16| {
+        let output = play_through(
+            &dir,
+            &format!("--fps 60 --loop 2 --stats --lookahead {lookahead}"),
+        )?;
+        let line = output
+            .lines()
+            .find(|line| line.starts_with("frames ready ahead"))
+            .unwrap();
+        let most = line.trim_end().trim_end_matches(" at most");
+        Some(most.rsplit(' ').next().unwrap().parse::<usize>().unwrap())
+    };
+    let (shallow, deep) = (most_ahead(1), most_ahead(4));
+    std::fs::remove_dir_all(&dir).unwrap();
+    let (Some(shallow), Some(deep)) = (shallow, deep) else {
+        return;
+    };
+    assert_eq!(shallow, 1);
+    // The buffer just handed back is still being filled when the next one is taken
+    assert!(deep >= 3, "{deep}");
+}
+
+#[test]
+fn dedupe() {
+    let dir = std::env::temp_dir().join(format!("ascii_art-{}-dedupe", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for i in 0..2 {
+        RgbImage::from_pixel(16, 8, Rgb([255, 255, 255]))
+            .save(dir.join(format!("{i}.png")))
+            .unwrap();
+    }
+    // Frames converted, as the convert row of --stats counts them, and the glyphs
+    // drawn
+    let play = |args: &str| {
+        let output = play_through(&dir, &format!("--loop 1 --diff --stats {args}"))?;
+        let converted = output
+            .lines()
+            .find_map(|line| line.strip_prefix("convert"))
+            .map(|row| {
+                row.split_whitespace()
+                    .next()
+                    .unwrap()
+                    .parse::<usize>()
+                    .unwrap()
+            })
+            .unwrap();
+        Some((converted, output.matches('@').count()))
+    };
+    let (plain, deduped) = (play(""), play("--dedupe"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    let (Some(plain), Some(deduped)) = (plain, deduped) else {
+        return;
+    };
+    assert_eq!(plain.0, 2);
+    assert_eq!(deduped.0, 1);
+    // The second frame draws nothing either way, once it's found the same
+    assert_eq!(deduped.1, plain.1);
+    assert!(deduped.1 > 0);
+}
+
+#[test]
+fn redirected() {
+    let path = image_file("redirected");
+    let out = path.with_extension("txt");
+    // Run from a terminal, but with stdout going to a file
+    let output = play_through(&path, &format!("--color truecolor > {}", out.display()));
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    let Some(output) = output else {
+        return;
+    };
+    assert!(written.contains("\x1b[38;2;"));
+    // Neither the file nor the terminal gets the cursor hidden or a screen switched
+    for escape in ["\x1b[?25l", "\x1b[?1049h"] {
+        assert!(!written.contains(escape), "{written:?}");
+        assert!(!output.contains(escape), "{output:?}");
+    }
+    assert!(cooked(&output), "{}", output);
+}
+
+#[test]
+fn idle() {
+    let fifo = std::env::temp_dir().join(format!("ascii_art-{}-idle", std::process::id()));
+    if !Command::new("mkfifo")
+        .arg(&fifo)
+        .status()
+        .unwrap()
+        .success()
+    {
+        return;
+    }
+    // A producer that stalls between its two frames, for longer than the player waits
+    let writer = {
+        let fifo = fifo.clone();
+        std::thread::spawn(move || {
+            let mut pipe = std::fs::OpenOptions::new().write(true).open(fifo).unwrap();
+            pipe.write_all(&[200; 4 * 2 * 3]).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(1500));
+            pipe.write_all(&[50; 4 * 2 * 3]).unwrap();
+        })
+    };
+    let output = play_through(&fifo, "--raw 4x2 --idle-timeout 1");
+    writer.join().unwrap();
+    std::fs::remove_file(&fifo).unwrap();
+    let Some(output) = output else {
+        return;
+    };
+    let waiting = output.find("waiting for frames, 1 s").unwrap();
+    // Wiped off once the next frame is drawn, which isn't late for having waited
+    assert!(
+        output[waiting..].contains("\x1b[0m\x1b[2K\x1b[1;1H"),
+        "{output:?}"
+    );
+    assert!(!output.contains("skipped"), "{output:?}");
+}
+
+#[test]
+fn resize() {
+    if !has_script() {
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("ascii_art-{}-resize", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for i in 0..2u8 {
+        RgbImage::from_pixel(160, 80, Rgb([i * 100, 50, 50]))
+            .save(dir.join(format!("{i}.png")))
+            .unwrap();
+    }
+    // The terminal grows to 60x20 once `grow` shows up, while the frames loop. Run in
+    // the background, the resize needs the terminal as its input
+    let grow = std::env::temp_dir().join(format!("ascii_art-{}-grow", std::process::id()));
+    let command = format!(
+        "stty cols 40 rows 12; \
+         (until [ -e {} ]; do sleep 0.05; done; stty cols 60 rows 20 < /dev/tty) & \
+         {} {} --status",
+        grow.display(),
+        env!("CARGO_BIN_EXE_ascii_art"),
+        dir.display()
+    );
+    let mut child = Command::new("script")
+        .args(["-qec", &command, "/dev/null"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let mut output = String::new();
+    let mut buf = [0; 4096];
+    // Quit once a frame is on screen after the clear for the resize, which waits for
+    // the first frame
+    let resized = |output: &str| {
+        output
+            .match_indices("\x1b[2J")
+            .nth(1)
+            .is_some_and(|(at, _)| output[at..].contains("frame "))
+    };
+    while !resized(&output) {
+        if output.contains("frame 1/") && !grow.exists() {
+            std::fs::write(&grow, "").unwrap();
+        }
+        let read = stdout.read(&mut buf).unwrap();
+        assert!(read > 0, "{output:?}");
+        output.push_str(&String::from_utf8_lossy(&buf[..read]));
+    }
+    child.stdin.take().unwrap().write_all(b"q").unwrap();
+    let mut rest = Vec::new();
+    stdout.read_to_end(&mut rest).unwrap();
+    assert!(child.wait().unwrap().success());
+    std::fs::remove_dir_all(&dir).unwrap();
+    std::fs::remove_file(&grow).unwrap();
+    // Row the status line is drawn on, below the frame
+    let status_row = |screen: &str| {
+        let (before, _) = screen.split_once("\x1b[0m\x1b[2Kframe ").unwrap();
+        let (_, position) = before.rsplit_once("\x1b[").unwrap();
+        position.split(';').next().unwrap().parse::<u32>().unwrap()
+    };
+    let (at, _) = output.match_indices("\x1b[2J").nth(1).unwrap();
+    let (before, after) = output.split_at(at);
+    // The frames after the resize are fitted to the larger terminal, wider and taller
+    let (small, large) = (status_row(before), status_row(after));
+    assert!(small <= 12 && large > small && large <= 20, "{output:?}");
+    // Columns the top row of the first frame takes, leaving out the escapes
+    let top_row = |screen: &str| {
+        let (_, frame) = screen.split_once("\x1b[0m\x1b[1;1H").unwrap();
+        let row = frame.split("\x1b[E").next().unwrap();
+        let row = row.split("\x1b[2;").next().unwrap();
+        row.split('\x1b')
+            .map(|part| part.split_once('m').map_or("", |(_, text)| text))
+            .map(|text| text.chars().count())
+            .sum::<usize>()
+    };
+    assert!(top_row(after) > top_row(before), "{output:?}");
+}
+
+/// Plays frames of one color each, `loops` times over and as fast as they come,
+/// converted `lookahead` ahead, and checks that each frame drawn is whole: in its
+/// own color throughout, set again by each block of rows, and as large as the rest.
+/// Returns the color of each frame drawn, `None` without `script`.
+fn play_colors(colors: &[[u8; 3]], loops: usize, lookahead: u16) -> Option<Vec<[u8; 3]>> {
+    let dir = std::env::temp_dir().join(format!(
+        "ascii_art-{}-colors-{lookahead}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (i, color) in colors.iter().enumerate() {
+        RgbImage::from_pixel(16, 8, Rgb(*color))
+            .save(dir.join(format!("{i}.png")))
+            .unwrap();
+    }
+    let args = format!(
+        "--uncapped --loop {loops} --force-truecolor --threads 4 --block-rows 1 \
+         --lookahead {lookahead}"
+    );
+    let output = play_through(&dir, &args);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let output = output?;
+    let screen = &output[output.find("\x1b[?1049h").unwrap()..output.find("\x1b[?1049l").unwrap()];
+    // Every frame starts at the top left corner, and the cursor goes back there
+    // after each one. The colors are reset in between
+    let mut size = None;
+    let shown = screen
+        .split("\x1b[1;1H")
+        .filter(|frame| frame.contains("\x1b[38;"))
+        .map(|frame| {
+            let mut color = None;
+            let mut cells = String::new();
+            for (j, part) in frame.split('\x1b').enumerate() {
+                let text = match part.find(|c: char| c.is_ascii_alphabetic()) {
+                    Some(end) if j > 0 => {
+                        let escape = &part[1..=end];
+                        if let Some(rgb) = escape.strip_prefix("38;2;") {
+                            let own = color.get_or_insert(rgb);
+                            assert_eq!(own, &rgb, "lookahead {lookahead}: {frame:?}");
+                        } else {
+                            assert!(!escape.ends_with('m') || escape == "0m", "{frame:?}");
+                        }
+                        &part[end + 1..]
+                    }
+                    _ => part,
+                };
+                cells.push_str(text);
+            }
+            let glyph = cells.chars().next().unwrap();
+            assert!(
+                *size.get_or_insert(cells.len()) == cells.len()
+                    && cells.chars().all(|cell| cell == glyph),
+                "lookahead {lookahead}: {frame:?}"
+            );
+            let rgb = color.unwrap().trim_end_matches('m');
+            let mut channels = rgb.split(';').map(|channel| channel.parse().unwrap());
+            [(); 3].map(|_| channels.next().unwrap())
+        })
+        .collect();
+    Some(shown)
+}
+
+#[test]
+fn frames_are_never_torn() {
+    // A frame drawn from a buffer that was being refilled would show two colors, and
+    // one the render thread missed or took twice would be out of step
+    let colors = (0..8u8)
+        .map(|i| [i * 30, 255 - i * 30, 100])
+        .collect::<Vec<_>>();
+    let loops = 50;
+    for lookahead in [1, 2, 4, 8] {
+        let Some(shown) = play_colors(&colors, loops, lookahead) else {
+            return;
+        };
+        let expected = colors.iter().copied().cycle().take(colors.len() * loops);
+        assert!(
+            shown.iter().copied().eq(expected),
+            "lookahead {lookahead}: {shown:?}"
+        );
+    }
+}